@@ -15,9 +15,14 @@
 
 use std::mem;
 
+pub use pack::*;
+pub use parse::*;
+
 use crate::syntax::VariantIndex;
 
 pub mod ook;
+mod pack;
+mod parse;
 pub mod spoon;
 
 /// Brainfuck instructions.
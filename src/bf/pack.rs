@@ -0,0 +1,74 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use bitvec::prelude::*;
+
+use crate::bf::Inst;
+use crate::syntax::VariantIndex;
+
+/// The number of bits that each instruction is packed to.
+const INST_BITS: usize = 3;
+
+/// Packs instructions into a compact encoding, with 3 bits per instruction.
+///
+/// The encoding is a little-endian `u64` count of instructions, followed by
+/// each instruction as its 3-bit index, most-significant bit first, packed
+/// across bytes and padded with zeros.
+#[must_use]
+pub fn pack(insts: &[Inst]) -> Vec<u8> {
+    let mut bits = BitVec::<u8, Msb0>::with_capacity(64 + insts.len() * INST_BITS);
+    bits.extend_from_raw_slice(&(insts.len() as u64).to_le_bytes());
+    for inst in insts {
+        let start = bits.len();
+        bits.resize(start + INST_BITS, false);
+        bits[start..].store_be(inst.index() as u8);
+    }
+    bits.into_vec()
+}
+
+/// Unpacks instructions from the encoding of [`pack`]. Returns `None`, if the
+/// bytes are too short for the count of instructions.
+#[must_use]
+pub fn unpack(bytes: &[u8]) -> Option<Vec<Inst>> {
+    if bytes.len() < 8 {
+        return None;
+    }
+    let (len, bits) = bytes.split_at(8);
+    let len = usize::try_from(u64::from_le_bytes(len.try_into().unwrap())).ok()?;
+    let bits = bits.view_bits::<Msb0>();
+    if bits.len() / INST_BITS < len {
+        return None;
+    }
+    let insts = bits
+        .chunks_exact(INST_BITS)
+        .take(len)
+        .map(|chunk| Inst::variant(u32::from(chunk.load_be::<u8>())))
+        .collect();
+    Some(insts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bf::Inst::*;
+
+    #[test]
+    fn round_trip() {
+        // ,[>+++[<.>-]<-]
+        let insts = [
+            Input, Head, Right, Inc, Inc, Inc, Head, Left, Output, Right, Dec, Tail, Left, Dec,
+            Tail,
+        ];
+        let packed = pack(&insts);
+        assert_eq!(8 + (insts.len() * 3 + 7) / 8, packed.len());
+        assert_eq!(Some(insts.to_vec()), unpack(&packed));
+        assert_eq!(None, unpack(&packed[..packed.len() - 1]));
+        assert_eq!(Some(Vec::new()), unpack(&pack(&[])));
+        assert_eq!(None, unpack(&[]));
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use crate::bf::Inst;
+
+/// An error from parsing a program with unbalanced loops, with the byte
+/// offset of the offending bracket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ParseError {
+    /// A `[` has no matching `]`. When several are unmatched, this is the
+    /// first.
+    UnmatchedHead { offset: usize },
+    /// A `]` has no matching `[`.
+    UnmatchedTail { offset: usize },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnmatchedHead { offset } => write!(f, "unmatched `[` at byte {offset}"),
+            ParseError::UnmatchedTail { offset } => write!(f, "unmatched `]` at byte {offset}"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parses a program and checks that its loops are balanced. Bytes other than
+/// the 8 instructions are comments.
+pub fn parse(src: &[u8]) -> Result<Vec<Inst>, ParseError> {
+    let mut insts = Vec::new();
+    let mut heads = Vec::new();
+    for (offset, &b) in src.iter().enumerate() {
+        let inst = match b {
+            b'>' => Inst::Right,
+            b'<' => Inst::Left,
+            b'+' => Inst::Inc,
+            b'-' => Inst::Dec,
+            b'.' => Inst::Output,
+            b',' => Inst::Input,
+            b'[' => {
+                heads.push(offset);
+                Inst::Head
+            }
+            b']' => {
+                if heads.pop().is_none() {
+                    return Err(ParseError::UnmatchedTail { offset });
+                }
+                Inst::Tail
+            }
+            _ => continue,
+        };
+        insts.push(inst);
+    }
+    match heads.first() {
+        Some(&offset) => Err(ParseError::UnmatchedHead { offset }),
+        None => Ok(insts),
+    }
+}
+
+/// The greatest depth of nested loops in a program. Unmatched `]` are
+/// ignored.
+#[must_use]
+pub fn max_loop_depth(insts: &[Inst]) -> usize {
+    let mut depth = 0usize;
+    let mut max = 0;
+    for inst in insts {
+        match inst {
+            Inst::Head => {
+                depth += 1;
+                max = max.max(depth);
+            }
+            Inst::Tail => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    max
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bf::Inst::*;
+
+    #[test]
+    fn balanced() {
+        let insts = parse(b"read: ,[>+++[<.>-]<-] done").unwrap();
+        assert_eq!(
+            vec![
+                Input, Head, Right, Inc, Inc, Inc, Head, Left, Output, Right, Dec, Tail, Left, Dec,
+                Tail,
+            ],
+            insts,
+        );
+        assert_eq!(2, max_loop_depth(&insts));
+        assert_eq!(0, max_loop_depth(&parse(b"+-.,").unwrap()));
+
+        let deep = [&[b'['; 1000][..], &[b']'; 1000]].concat();
+        assert_eq!(1000, max_loop_depth(&parse(&deep).unwrap()));
+    }
+
+    #[test]
+    fn unbalanced() {
+        assert_eq!(
+            Err(ParseError::UnmatchedHead { offset: 1 }),
+            parse(b"+[[-]")
+        );
+        assert_eq!(Err(ParseError::UnmatchedHead { offset: 0 }), parse(b"[[["));
+        assert_eq!(
+            Err(ParseError::UnmatchedTail { offset: 4 }),
+            parse(b"[-] ]")
+        );
+        assert_eq!(
+            "unmatched `]` at byte 0",
+            parse(b"][").unwrap_err().to_string()
+        );
+    }
+}
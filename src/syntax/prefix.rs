@@ -201,6 +201,20 @@ impl<T: VariantIndex, O> ConflictError<T, O> {
     const fn new(prefix: TokenSeq<T>, opcodes: Vec<O>) -> Self {
         ConflictError { prefix, opcodes }
     }
+
+    /// The sequence at which the conflict was found.
+    #[inline]
+    #[must_use]
+    pub const fn prefix(&self) -> TokenSeq<T> {
+        self.prefix
+    }
+
+    /// The opcodes that conflict, with the opcode being inserted last.
+    #[inline]
+    #[must_use]
+    pub fn opcodes(&self) -> &[O] {
+        &self.opcodes
+    }
 }
 
 pub trait Tokens {
@@ -7,7 +7,8 @@
 // Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
 
 use std::cmp::Ordering;
-use std::fmt::{self, Debug, Formatter};
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 
@@ -15,7 +16,12 @@ use crate::syntax::VariantIndex;
 
 /// A compact token stack, that represents a sequence of tokens as a scalar.
 ///
-/// `TokenSeq` can be used to order or hash token sequences. For example, with
+/// `TokenSeq` can be used to order or hash token sequences and is the key type
+/// of prefix tables, so it suits short sequences, like opcodes. Longer
+/// sequences, like whole programs, should use a `Vec` of tokens, which can be
+/// extended with bits with [`TokenVec`](crate::ws::token::TokenVec).
+///
+/// For example, with
 /// [`ws::Token`](crate::ws::Token), which has 3 variants—`S`, `T`, and `L`—,
 /// `[]` is represented by a `TokenSeq` of 0, `[S]` is 1, `[T]` is 2, `[L]` is
 /// 3, `[S S]` is 4, `[S T]` is 5, etc.
@@ -41,6 +47,11 @@ use crate::syntax::VariantIndex;
 /// - 41..=84 variants => capacity 6
 /// - 85..=255 variants => capacity 5
 /// - …
+///
+/// Pushing past the capacity or popping from an empty sequence panics, in
+/// both debug and release builds, rather than corrupting the sequence.
+/// Sequences of runtime length should be converted with [`TryFrom`] or built
+/// with [`try_push`](TokenSeq::try_push).
 #[repr(transparent)]
 pub struct TokenSeq<T> {
     inner: u32,
@@ -59,6 +70,34 @@ impl<T: VariantIndex> TokenSeq<T> {
         TokenSeq { inner: 0, elem: PhantomData }
     }
 
+    /// Builds a sequence from the variant indices of its tokens, so that
+    /// sequences can be built in const contexts, where
+    /// [`VariantIndex::index`] cannot be called. For Whitespace tokens, use
+    /// [`token_seq!`](crate::token_seq).
+    ///
+    /// # Panics
+    ///
+    /// Panics, if an index is not less than
+    /// [`COUNT`](VariantIndex::COUNT), or if the sequence exceeds the
+    /// capacity.
+    #[must_use]
+    pub const fn from_indices(indices: &[u32]) -> Self {
+        let mut inner: u32 = 0;
+        let mut i = 0;
+        while i < indices.len() {
+            assert!(indices[i] < T::COUNT, "token index out of range");
+            inner = match inner.checked_mul(T::COUNT) {
+                Some(n) => match n.checked_add(indices[i] + 1) {
+                    Some(n) => n,
+                    None => panic!("token sequence exceeds capacity"),
+                },
+                None => panic!("token sequence exceeds capacity"),
+            };
+            i += 1;
+        }
+        TokenSeq { inner, elem: PhantomData }
+    }
+
     #[inline]
     #[must_use]
     pub const fn size_for(width: usize) -> usize {
@@ -71,20 +110,45 @@ impl<T: VariantIndex> TokenSeq<T> {
         max as usize + 1
     }
 
+    /// Appends a token.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if the sequence is at capacity.
     #[inline]
     pub fn push(&mut self, tok: &T) {
+        self.try_push(tok).unwrap_or_else(|err| panic!("{err}"));
+    }
+
+    /// Appends a token, or returns an error and leaves the sequence unchanged,
+    /// if it is at capacity.
+    #[inline]
+    pub fn try_push(&mut self, tok: &T) -> Result<(), CapacityError> {
         let v = tok.index();
         debug_assert!(v < T::COUNT);
-        self.inner = self.inner * T::COUNT + v + 1;
+        self.inner = (self.inner.checked_mul(T::COUNT))
+            .and_then(|inner| inner.checked_add(v + 1))
+            .ok_or(CapacityError)?;
+        Ok(())
     }
 
+    /// Removes the last token.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if the sequence is empty.
     #[inline]
     pub fn pop(&mut self) -> T {
-        let v = (self.inner - 1) % T::COUNT;
-        debug_assert!(v < T::COUNT);
-        let tok = T::variant(v);
-        self.inner = (self.inner - 1) / T::COUNT;
-        tok
+        self.try_pop().expect("pop from empty token sequence")
+    }
+
+    /// Removes the last token, or returns `None`, if the sequence is empty.
+    #[inline]
+    pub fn try_pop(&mut self) -> Option<T> {
+        let inner = self.inner.checked_sub(1)?;
+        let tok = T::variant(inner % T::COUNT);
+        self.inner = inner / T::COUNT;
+        Some(tok)
     }
 
     #[inline]
@@ -110,6 +174,90 @@ impl<T: VariantIndex> TokenSeq<T> {
     pub const fn as_usize(&self) -> usize {
         self.inner as usize
     }
+
+    /// Packs the variant indices of the tokens into the low bits of a `u64`,
+    /// with the fewest bits per token that fit every variant and the last
+    /// token in the lowest bits. The high bits, beyond the tokens, are clear,
+    /// so this is the data of the sequence without its length, for building
+    /// compound keys.
+    ///
+    /// Leading tokens with index 0 pack to zero bits, so sequences of
+    /// different lengths can have the same data bits, and this is only
+    /// meaningful alongside [`len`](TokenSeq::len).
+    #[must_use]
+    pub const fn data_bits(&self) -> u64 {
+        let width = u32::BITS - (T::COUNT - 1).leading_zeros();
+        let mut seq = self.inner;
+        let mut bits = 0;
+        let mut shift = 0;
+        while seq != 0 {
+            bits |= (((seq - 1) % T::COUNT) as u64) << shift;
+            seq = (seq - 1) / T::COUNT;
+            shift += width;
+        }
+        bits
+    }
+
+    /// Rotates the tokens in place, so that the first `n` tokens move to the
+    /// end. Rotating by a multiple of the length is a no-op.
+    ///
+    /// The capacity depends on the tokens, so a full sequence may not fit
+    /// once rotated, in which case an error is returned and the sequence is
+    /// left unchanged.
+    #[inline]
+    pub const fn rotate_left(&mut self, n: usize) -> Result<(), CapacityError> {
+        let len = self.len() as usize;
+        if len == 0 {
+            return Ok(());
+        }
+        self.rotate_split(len - n % len)
+    }
+
+    /// Rotates the tokens in place, so that the last `n` tokens move to the
+    /// front. Rotating by a multiple of the length is a no-op. As with
+    /// [`rotate_left`](TokenSeq::rotate_left), an error is returned when the
+    /// rotated sequence exceeds the capacity.
+    #[inline]
+    pub const fn rotate_right(&mut self, n: usize) -> Result<(), CapacityError> {
+        let len = self.len() as usize;
+        if len == 0 {
+            return Ok(());
+        }
+        self.rotate_split(n % len)
+    }
+
+    /// Moves the last `n` tokens to the front, operating directly on the
+    /// packed digits, which are each a token index plus 1.
+    const fn rotate_split(&mut self, n: usize) -> Result<(), CapacityError> {
+        let mut high = self.inner;
+        let mut low: u32 = 0;
+        let mut scale: u32 = 1;
+        let mut i = 0;
+        while i < n {
+            let digit = (high - 1) % T::COUNT + 1;
+            high = (high - 1) / T::COUNT;
+            // The low tokens are a suffix of the sequence, so they fit.
+            low += digit * scale;
+            scale = scale.saturating_mul(T::COUNT);
+            i += 1;
+        }
+        // `high` now holds the first `len - n` tokens, which follow the low
+        // `n` tokens.
+        let mut shifted = low;
+        let mut rest = high;
+        while rest != 0 {
+            rest = (rest - 1) / T::COUNT;
+            shifted = match shifted.checked_mul(T::COUNT) {
+                Some(shifted) => shifted,
+                None => return Err(CapacityError),
+            };
+        }
+        self.inner = match shifted.checked_add(high) {
+            Some(inner) => inner,
+            None => return Err(CapacityError),
+        };
+        Ok(())
+    }
 }
 
 impl<T> From<u32> for TokenSeq<T> {
@@ -130,19 +278,47 @@ impl<T> From<usize> for TokenSeq<T> {
     }
 }
 
-impl<T: VariantIndex> From<&[T]> for TokenSeq<T> {
-    fn from(toks: &[T]) -> Self {
+/// An error from building a [`TokenSeq`] with more tokens than its capacity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CapacityError;
+
+impl Display for CapacityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("token sequence exceeds capacity")
+    }
+}
+
+impl Error for CapacityError {}
+
+impl<T: VariantIndex> TryFrom<&[T]> for TokenSeq<T> {
+    type Error = CapacityError;
+
+    /// Converts a slice of tokens, or returns an error, if it has more tokens
+    /// than the capacity.
+    fn try_from(toks: &[T]) -> Result<Self, Self::Error> {
         let mut seq = TokenSeq::new();
         for tok in toks {
-            seq.push(tok);
+            seq.try_push(tok)?;
         }
-        seq
+        Ok(seq)
     }
 }
 
 impl<T: VariantIndex, const N: usize> From<&[T; N]> for TokenSeq<T> {
+    /// Converts an array of tokens, for literals with a known length.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tokens do not fit. Below the ceiling of the capacity,
+    /// every sequence fits, but at the ceiling, whether it fits depends on the
+    /// tokens. For example, with the 3 variants of
+    /// [`ws::Token`](crate::ws::Token), every sequence of 19 tokens fits, but
+    /// a sequence of 20 tokens fits only when its scalar does not exceed
+    /// `u32::MAX`, so `[S; 20]` fits and `[L; 20]` does not. Sequences of
+    /// runtime length should be converted with [`TryFrom<&[T]>`](TryFrom)
+    /// instead.
     fn from(toks: &[T; N]) -> Self {
-        TokenSeq::from(toks.as_slice())
+        TokenSeq::try_from(toks.as_slice()).unwrap()
     }
 }
 
@@ -158,6 +334,17 @@ impl<T: VariantIndex> From<TokenSeq<T>> for Vec<T> {
     }
 }
 
+impl<T: VariantIndex> IntoIterator for TokenSeq<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    /// Iterates the tokens from first to last.
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        Vec::from(self).into_iter()
+    }
+}
+
 impl<T: Debug + VariantIndex> Debug for TokenSeq<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_tuple("TokenSeq")
@@ -226,10 +413,114 @@ mod tests {
         ];
         for (i, &toks) in seqs.iter().enumerate() {
             let seq = TokenSeq::from(i);
-            let seq2 = TokenSeq::from(toks);
+            let seq2 = TokenSeq::try_from(toks).unwrap();
             assert_eq!(seq, seq2, "TokenSeq::from({toks:?})");
             let toks2 = Vec::from(seq);
             assert_eq!(toks, toks2, "TokenVec::from({seq:?})");
+            assert!(seq.into_iter().eq(toks.iter().copied()));
+        }
+    }
+
+    #[test]
+    fn data_bits() {
+        let seq = |toks: &[Token]| TokenSeq::try_from(toks).unwrap();
+        assert_eq!(0, seq(&[]).data_bits());
+        assert_eq!(0b00_01_10, seq(&[S, T, L]).data_bits());
+        assert_eq!(0b10_10, seq(&[S, S, L, L]).data_bits());
+        assert_eq!(seq(&[T, L, S]).data_bits(), seq(&[T, L, S]).data_bits());
+        // Equal data bits need equal lengths to be the same sequence.
+        assert_eq!(seq(&[S, T]).data_bits(), seq(&[T]).data_bits());
+        assert_ne!(seq(&[S, T]).len(), seq(&[T]).len());
+        let max = TokenSeq::<Token>::MAX;
+        assert!(max.data_bits() < 1 << (2 * max.len()));
+    }
+
+    #[test]
+    fn round_trip_lengths() {
+        // Every sequence of up to 19 tokens fits, regardless of its tokens.
+        for len in 0..=19 {
+            let toks = (0..len).map(|i| Token::all()[i % 3]).collect::<Vec<_>>();
+            let seq = TokenSeq::from(toks.clone());
+            assert_eq!(len, seq.len() as usize);
+            assert_eq!(seq, TokenSeq::try_from(&toks[..]).unwrap());
+            assert_eq!(toks, Vec::from(seq), "len {len}");
+            let mut popped = Vec::new();
+            let mut seq = seq;
+            while !seq.is_empty() {
+                popped.push(seq.pop());
+            }
+            popped.reverse();
+            assert_eq!(toks, popped, "len {len}");
         }
+        assert!(TokenSeq::try_from(&[L; 20][..]).is_err());
+    }
+
+    #[test]
+    fn capacity() {
+        assert_eq!(20, TokenSeq::try_from(&[S; 20][..]).unwrap().len());
+        assert_eq!(Err(CapacityError), TokenSeq::try_from(&[S; 21][..]));
+        assert_eq!(Err(CapacityError), TokenSeq::try_from(&[L; 20][..]));
+        let mut seq = TokenSeq::from(&[L; 19]);
+        let before = seq;
+        assert_eq!(Err(CapacityError), seq.try_push(&L));
+        assert_eq!(before, seq);
+
+        let mut seq = TokenSeq::<Token>::new();
+        assert_eq!(None, seq.try_pop());
+        assert_eq!(TokenSeq::new(), seq);
+    }
+
+    #[test]
+    #[should_panic(expected = "token sequence exceeds capacity")]
+    fn push_past_capacity() {
+        let mut seq = TokenSeq::from(&[L; 19]);
+        seq.push(&L);
+    }
+
+    #[test]
+    #[should_panic(expected = "pop from empty token sequence")]
+    fn pop_empty() {
+        TokenSeq::<Token>::new().pop();
+    }
+
+    #[test]
+    fn rotate() {
+        let toks = [S, T, L, L, S, T, T];
+        for n in 0..=2 * toks.len() {
+            let mut seq = TokenSeq::from(&toks);
+            seq.rotate_left(n).unwrap();
+            let mut expected = toks;
+            expected.rotate_left(n % toks.len());
+            assert_eq!(TokenSeq::from(&expected), seq, "rotate_left({n})");
+
+            let mut seq = TokenSeq::from(&toks);
+            seq.rotate_right(n).unwrap();
+            let mut expected = toks;
+            expected.rotate_right(n % toks.len());
+            assert_eq!(TokenSeq::from(&expected), seq, "rotate_right({n})");
+        }
+        let mut seq = TokenSeq::<Token>::new();
+        assert_eq!(0, seq.into_iter().count());
+        seq.rotate_left(3).unwrap();
+        seq.rotate_right(3).unwrap();
+        assert!(seq.is_empty());
+
+        // A full sequence does not fit once its high last token moves to the
+        // front.
+        let mut toks = [S; 20];
+        toks[0] = T;
+        toks[19] = L;
+        let mut seq = TokenSeq::from(&toks);
+        let before = seq;
+        assert_eq!(Err(CapacityError), seq.rotate_right(1));
+        assert_eq!(before, seq);
+        assert_eq!(Ok(()), seq.rotate_left(20));
+        assert_eq!(before, seq);
+        assert_eq!(Ok(()), seq.rotate_left(1));
+        toks.rotate_left(1);
+        assert_eq!(TokenSeq::from(&toks), seq);
+        let mut seq = TokenSeq::from(&[S; 20]);
+        assert_eq!(Ok(()), seq.rotate_left(7));
+        assert_eq!(TokenSeq::from(&[S; 20]), seq);
     }
 }
@@ -6,6 +6,8 @@
 // later version. You should have received a copy of the GNU Lesser General
 // Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
 
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 use std::iter::FusedIterator;
 
 use arrayvec::ArrayVec;
@@ -16,6 +18,16 @@ pub enum EncodingError {
     InvalidUtf8(ArrayVec<u8, 3>),
 }
 
+impl Display for EncodingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodingError::InvalidUtf8(bytes) => write!(f, "invalid UTF-8 sequence {bytes:02x?}"),
+        }
+    }
+}
+
+impl Error for EncodingError {}
+
 #[derive(Clone, Debug)]
 pub struct Utf8Iterator<'a> {
     src: &'a [u8],
@@ -114,8 +126,16 @@ impl Iterator for ByteIterator<'_> {
         self.offset += 1;
         Some(Ok(b))
     }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.src.len() - self.offset;
+        (len, Some(len))
+    }
 }
 
+impl ExactSizeIterator for ByteIterator<'_> {}
+
 impl FusedIterator for ByteIterator<'_> {}
 
 #[cfg(test)]
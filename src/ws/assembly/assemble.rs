@@ -0,0 +1,356 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+//! A minimal line-oriented assembler and disassembler.
+//!
+//! Each line has an optional label definition of the form `name:`, followed
+//! by an optional instruction, written as a mnemonic and, for instructions
+//! with arguments, one argument. Mnemonics are case-insensitive and ignore
+//! underscores, so `dump_stack` and `DumpStack` are equivalent.
+//!
+//! Integer arguments are C-like or Erlang-like literals, as parsed by
+//! [`IntLiteral`]. Label arguments are either a decimal integer, `.` followed
+//! by the exact bits of the label (e.g., `.0100`), or a name, which is
+//! assigned bits that do not conflict with other labels.
+//!
+//! Comments start with `;` (or any of the other line and block comment styles
+//! of the lexer) and continue to the end of the line. Blank lines are
+//! ignored.
+//!
+//! The `.tokens` directive, in place of an instruction, injects literal tokens
+//! written with `S`, `T`, and `L`, as in `.tokens STL SSTL`, for sequences that
+//! the mnemonics cannot express. The tokens bypass the checks on mnemonics and
+//! arguments, but must form whole instructions on each line, and labels in
+//! them are used verbatim, without affecting the bits chosen for named labels,
+//! other than avoiding conflicts.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter, Write};
+use std::sync::LazyLock;
+
+use bitvec::vec::BitVec;
+use rug::Integer;
+
+use crate::ws::assembly::lex::{tokenize, TokenKind};
+use crate::ws::assembly::MnemonicMap;
+use crate::ws::inst::{Inst, InstArg, InstError, Opcode, RawInst};
+use crate::ws::parse::{ParseError, Parser};
+use crate::ws::program::LabelGen;
+use crate::ws::syntax::{convert, IntLiteral, NumberEncoding, ParseError as IntParseError};
+use crate::ws::token::Token;
+
+static MNEMONICS: LazyLock<MnemonicMap> = LazyLock::new(MnemonicMap::with_permissive);
+
+/// An error from assembling, with the 1-based line at which it occurred.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AssembleError {
+    UnknownMnemonic {
+        line: usize,
+        mnemonic: String,
+    },
+    MissingArg {
+        line: usize,
+        opcode: Opcode,
+    },
+    UnexpectedToken {
+        line: usize,
+        token: String,
+    },
+    InvalidInt {
+        line: usize,
+        err: IntParseError,
+    },
+    /// The tokens of a `.tokens` directive do not form whole instructions.
+    InvalidTokens {
+        line: usize,
+        err: ParseError,
+    },
+}
+
+impl Display for AssembleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AssembleError::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "line {line}: unknown mnemonic `{mnemonic}`")
+            }
+            AssembleError::MissingArg { line, opcode } => {
+                write!(f, "line {line}: missing argument to {opcode}")
+            }
+            AssembleError::UnexpectedToken { line, token } => {
+                write!(f, "line {line}: unexpected token `{token}`")
+            }
+            AssembleError::InvalidInt { line, err } => {
+                write!(f, "line {line}: invalid integer: {err}")
+            }
+            AssembleError::InvalidTokens { line, err } => {
+                write!(f, "line {line}: invalid tokens: {err}")
+            }
+        }
+    }
+}
+
+impl Error for AssembleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AssembleError::InvalidInt { err, .. } => Some(err),
+            AssembleError::InvalidTokens { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Assembles a program from the textual assembly format.
+#[inline]
+pub fn assemble(src: &str) -> Result<Vec<RawInst>, AssembleError> {
+    assemble_with(src, NumberEncoding::SignMagnitude)
+}
+
+/// Assembles a program like [`assemble`], encoding integer arguments with the
+/// given encoding. Under the standard sign-magnitude encoding, the sign and
+/// leading zeros of literals are kept; under others, integers are encoded in
+/// the fewest bits.
+pub fn assemble_with(src: &str, encoding: NumberEncoding) -> Result<Vec<RawInst>, AssembleError> {
+    let mut items = Vec::new();
+    let mut line_toks = Vec::new();
+    let (mut line, mut offset) = (1, 0);
+    for tok in tokenize(src) {
+        let text = &src[offset..offset + tok.len as usize];
+        offset += tok.len as usize;
+        match tok.kind {
+            TokenKind::Lf => {
+                parse_line(&line_toks, line, &mut items)?;
+                line_toks.clear();
+            }
+            TokenKind::Whitespace
+            | TokenKind::LineComment { .. }
+            | TokenKind::BlockComment { .. } => {}
+            kind => line_toks.push((kind, text)),
+        }
+        line += text.matches('\n').count();
+    }
+    parse_line(&line_toks, line, &mut items)?;
+
+    // Assign bits to named labels, that do not conflict with literal labels.
+    let mut labels = LabelGen::avoiding(items.iter().filter_map(|item| match item {
+        Item::Label(text) | Item::Inst(_, Some(text), _) => literal_label(text),
+        Item::Raw(
+            Inst::Label(bits)
+            | Inst::Call(bits)
+            | Inst::Jmp(bits)
+            | Inst::Jz(bits)
+            | Inst::Jn(bits),
+        ) => Some(bits.clone()),
+        Item::Inst(_, None, _) | Item::Raw(_) => None,
+    }));
+    let mut names = HashMap::new();
+    let mut label_bits = |text: &str| -> BitVec {
+        if let Some(bits) = literal_label(text) {
+            return bits;
+        }
+        (names.entry(text.to_owned()))
+            .or_insert_with(|| labels.next_label())
+            .clone()
+    };
+
+    let mut insts = Vec::with_capacity(items.len());
+    for item in items {
+        let (opcode, arg, line) = match item {
+            Item::Label(name) => (Opcode::Label, Some(name), 0),
+            Item::Inst(opcode, arg, line) => (opcode, arg, line),
+            Item::Raw(inst) => {
+                insts.push(inst);
+                continue;
+            }
+        };
+        let arg = match (arg_kind(opcode), arg) {
+            (None, _) => None,
+            (Some(_), None) => return Err(AssembleError::MissingArg { line, opcode }),
+            (Some(InstArg::Int(())), Some(text)) => {
+                let int = if text.contains('#') {
+                    IntLiteral::parse_erlang_style(text)
+                } else {
+                    IntLiteral::parse_c_style(text)
+                };
+                let int = int.map_err(|err| AssembleError::InvalidInt { line, err })?;
+                Some(InstArg::Int(match encoding {
+                    NumberEncoding::SignMagnitude => int.into_bits(),
+                    NumberEncoding::TwosComplement => encoding.encode(&int),
+                }))
+            }
+            (Some(InstArg::Label(())), Some(text)) => Some(InstArg::Label(label_bits(text))),
+        };
+        insts.push(Inst::from(opcode).map_arg(|_, _| Ok::<_, ParseError>(arg.unwrap())));
+    }
+    Ok(insts)
+}
+
+enum Item<'a> {
+    Label(&'a str),
+    Inst(Opcode, Option<&'a str>, usize),
+    Raw(RawInst),
+}
+
+fn parse_line<'a>(
+    mut toks: &[(TokenKind, &'a str)],
+    line: usize,
+    items: &mut Vec<Item<'a>>,
+) -> Result<(), AssembleError> {
+    if let [(TokenKind::Word | TokenKind::Int { .. }, name), (TokenKind::Colon, _), rest @ ..] =
+        toks
+    {
+        items.push(Item::Label(name));
+        toks = rest;
+    }
+    let Some(&(kind, mnemonic)) = toks.first() else {
+        return Ok(());
+    };
+    if kind != TokenKind::Word {
+        return Err(AssembleError::UnexpectedToken { line, token: mnemonic.to_owned() });
+    }
+    if mnemonic == ".tokens" {
+        return parse_tokens(&toks[1..], line, items);
+    }
+    let normalized = mnemonic.to_lowercase().replace('_', "");
+    let Some(opcode) = MNEMONICS.get(&normalized) else {
+        return Err(AssembleError::UnknownMnemonic {
+            line,
+            mnemonic: mnemonic.to_owned(),
+        });
+    };
+    let arg = match (arg_kind(opcode), &toks[1..]) {
+        (_, []) => None,
+        (Some(_), [(_, arg)]) => Some(*arg),
+        (Some(_), [_, (_, token), ..]) | (None, [(_, token), ..]) => {
+            return Err(AssembleError::UnexpectedToken { line, token: (*token).to_owned() });
+        }
+    };
+    items.push(Item::Inst(opcode, arg, line));
+    Ok(())
+}
+
+/// Parses the arguments of a `.tokens` directive into instructions.
+fn parse_tokens(
+    toks: &[(TokenKind, &str)],
+    line: usize,
+    items: &mut Vec<Item<'_>>,
+) -> Result<(), AssembleError> {
+    let mut seq = Vec::new();
+    for &(kind, text) in toks {
+        let unexpected = || AssembleError::UnexpectedToken { line, token: text.to_owned() };
+        if kind != TokenKind::Word {
+            return Err(unexpected());
+        }
+        for ch in text.chars() {
+            seq.push(match ch {
+                'S' => Token::S,
+                'T' => Token::T,
+                'L' => Token::L,
+                _ => return Err(unexpected()),
+            });
+        }
+    }
+    for inst in Parser::new(seq.into_iter().map(Ok)) {
+        if let Inst::Error(InstError::ParseError(err)) = inst {
+            return Err(AssembleError::InvalidTokens { line, err });
+        }
+        items.push(Item::Raw(inst));
+    }
+    Ok(())
+}
+
+/// Parses a label that is written as bits or as a decimal integer.
+fn literal_label(text: &str) -> Option<BitVec> {
+    if let Some(bits) = text.strip_prefix('.') {
+        return bits
+            .chars()
+            .map(|ch| match ch {
+                '0' => Some(false),
+                '1' => Some(true),
+                _ => None,
+            })
+            .collect();
+    }
+    if !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()) {
+        let uint = Integer::from_str_radix(text, 10).ok()?;
+        return Some(convert::unsigned_bits_from_integer(&uint));
+    }
+    None
+}
+
+fn arg_kind(opcode: Opcode) -> Option<InstArg<(), ()>> {
+    match Inst::from(opcode) {
+        Inst::Push(()) | Inst::Copy(()) | Inst::Slide(()) => Some(InstArg::Int(())),
+        Inst::Label(()) | Inst::Call(()) | Inst::Jmp(()) | Inst::Jz(()) | Inst::Jn(()) => {
+            Some(InstArg::Label(()))
+        }
+        _ => None,
+    }
+}
+
+/// Disassembles a program to the textual assembly format, so that assembling
+/// the result produces the same instructions. Instructions that failed to
+/// parse are written as comments.
+#[inline]
+#[must_use]
+pub fn disassemble(insts: &[RawInst]) -> String {
+    disassemble_with(insts, NumberEncoding::SignMagnitude)
+}
+
+/// Disassembles a program like [`disassemble`], decoding integer arguments
+/// with the given encoding, so that [`assemble_with`] with the same encoding
+/// produces instructions with the same values. Only under the standard
+/// sign-magnitude encoding are the bits kept exactly.
+#[must_use]
+pub fn disassemble_with(insts: &[RawInst], encoding: NumberEncoding) -> String {
+    let mut s = String::new();
+    for inst in insts {
+        match inst {
+            Inst::Label(l) => writeln!(s, "{}:", label_text(l)),
+            Inst::Push(n) | Inst::Copy(n) | Inst::Slide(n) => {
+                writeln!(s, "    {} {}", inst.opcode(), int_text(n, encoding))
+            }
+            Inst::Call(l) | Inst::Jmp(l) | Inst::Jz(l) | Inst::Jn(l) => {
+                writeln!(s, "    {} {}", inst.opcode(), label_text(l))
+            }
+            Inst::Error(err) => writeln!(s, "    ; error: {err:?}"),
+            _ => writeln!(s, "    {}", inst.opcode()),
+        }
+        .unwrap();
+    }
+    s
+}
+
+fn int_text(bits: &BitVec, encoding: NumberEncoding) -> String {
+    let decimal = encoding.decode(bits).to_string();
+    if encoding != NumberEncoding::SignMagnitude {
+        return decimal;
+    }
+    if matches!(IntLiteral::parse_c_style(&decimal), Ok(int) if int.bits() == bits) {
+        return decimal;
+    }
+    // Write numbers with leading zeros or only a sign in base 2
+    let sign = match bits.first().as_deref() {
+        Some(true) => "-",
+        Some(false) => "+",
+        None => "",
+    };
+    let bin = bits.iter().skip(1).map(|b| if *b { '1' } else { '0' });
+    format!("{sign}b#{}", bin.collect::<String>())
+}
+
+/// Writes a label as in the assembly format: in decimal, when it has no
+/// leading zeros, or in binary after a `.`, otherwise.
+#[must_use]
+pub fn label_text(bits: &BitVec) -> String {
+    if let Some(uint) = convert::integer_from_unsigned_bits_unambiguous(bits) {
+        return uint.to_string();
+    }
+    let bin = bits.iter().map(|b| if *b { '1' } else { '0' });
+    format!(".{}", bin.collect::<String>())
+}
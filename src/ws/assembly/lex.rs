@@ -91,6 +91,18 @@ pub enum Base {
     Hexadecimal,
 }
 
+/// Creates an iterator that produces tokens from the input string.
+pub(crate) fn tokenize(input: &str) -> impl Iterator<Item = Token> + '_ {
+    let mut cursor = Cursor::new(input);
+    std::iter::from_fn(move || {
+        if cursor.is_eof() {
+            return None;
+        }
+        cursor.reset_len_consumed();
+        Some(cursor.advance_token())
+    })
+}
+
 impl Cursor<'_> {
     /// Scans a token from the input string.
     fn advance_token(&mut self) -> Token {
@@ -136,7 +148,13 @@ impl Cursor<'_> {
     #[inline]
     fn signed_int_or_word(&mut self) -> TokenKind {
         debug_assert!(matches!(self.prev(), '+' | '-'));
-        todo!()
+        match self.first() {
+            first_digit @ '0'..='9' => {
+                self.bump();
+                self.unsigned_int(first_digit)
+            }
+            _ => self.word(),
+        }
     }
 
     #[inline]
@@ -74,6 +74,12 @@ impl MnemonicMap {
         Ok(())
     }
 
+    #[inline]
+    #[must_use]
+    pub fn get(&self, mnemonic: &str) -> Option<Opcode> {
+        self.mnemonics.get(mnemonic).copied()
+    }
+
     #[inline]
     pub fn insert(&mut self, opcode: Opcode, mnemonic: &str) -> Result<(), MnemonicError> {
         self.insert_compact(opcode, mnemonic.to_compact_string())
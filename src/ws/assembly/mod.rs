@@ -6,9 +6,11 @@
 // later version. You should have received a copy of the GNU Lesser General
 // Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
 
+pub use assemble::*;
 pub(crate) use cursor::*;
 pub use mnemonics::*;
 
+mod assemble;
 #[allow(dead_code)]
 mod cursor;
 #[allow(dead_code)]
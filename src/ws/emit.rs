@@ -0,0 +1,26 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use crate::ws::inst::RawInst;
+use crate::ws::token::Mapping;
+
+/// Emits a program as Whitespace source with the standard space, tab, and line
+/// feed characters, so that it can be run by other interpreters.
+///
+/// # Panics
+///
+/// Panics if any instruction is [`Inst::Error`](crate::ws::inst::Inst::Error).
+#[must_use]
+pub fn emit_canonical(insts: &[RawInst]) -> String {
+    let map = Mapping::<char>::default();
+    let mut toks = Vec::new();
+    for inst in insts {
+        inst.encode(&mut toks);
+    }
+    toks.iter().map(|&tok| *map.map_token(tok)).collect()
+}
@@ -0,0 +1,85 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+use crate::text::EncodingError;
+use crate::ws::assembly::AssembleError;
+use crate::ws::inst::InstError;
+use crate::ws::parse::ParseError;
+use crate::ws::token::BitUnpackError;
+use crate::ws::vm::RuntimeError;
+
+/// Any error from lexing, parsing, assembling, or running a Whitespace
+/// program, so that a pipeline of stages can propagate errors with `?`.
+#[derive(Debug)]
+pub enum WsError {
+    Encoding(EncodingError),
+    Parse(ParseError),
+    BitUnpack(BitUnpackError),
+    Assemble(AssembleError),
+    Runtime(RuntimeError),
+    Io(io::Error),
+}
+
+impl Display for WsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            WsError::Encoding(err) => write!(f, "encoding error: {err}"),
+            WsError::Parse(err) => write!(f, "parse error: {err}"),
+            WsError::BitUnpack(err) => write!(f, "bit unpack error: {err}"),
+            WsError::Assemble(err) => write!(f, "assemble error: {err}"),
+            WsError::Runtime(err) => write!(f, "runtime error: {err}"),
+            WsError::Io(err) => write!(f, "I/O error: {err}"),
+        }
+    }
+}
+
+impl Error for WsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WsError::Encoding(err) => err.source(),
+            WsError::Parse(err) => err.source(),
+            WsError::BitUnpack(err) => err.source(),
+            WsError::Assemble(err) => err.source(),
+            WsError::Runtime(err) => err.source(),
+            WsError::Io(err) => err.source(),
+        }
+    }
+}
+
+macro_rules! from_error(($($Variant:ident($Err:ty)),+ $(,)?) => {
+    $(
+        impl From<$Err> for WsError {
+            #[inline]
+            fn from(err: $Err) -> Self {
+                WsError::$Variant(err)
+            }
+        }
+    )+
+});
+
+from_error! {
+    Encoding(EncodingError),
+    Parse(ParseError),
+    BitUnpack(BitUnpackError),
+    Assemble(AssembleError),
+    Runtime(RuntimeError),
+    Io(io::Error),
+}
+
+impl From<InstError> for WsError {
+    #[inline]
+    fn from(err: InstError) -> Self {
+        match err {
+            InstError::ParseError(err) => WsError::Parse(err),
+        }
+    }
+}
@@ -6,6 +6,7 @@
 // later version. You should have received a copy of the GNU Lesser General
 // Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
 
+use std::error::Error;
 use std::fmt::{self, Display, Formatter};
 use std::mem;
 
@@ -16,7 +17,8 @@ use strum::{Display, IntoStaticStr};
 
 use crate::syntax::{Tokens, VariantIndex};
 use crate::ws::parse::ParseError;
-use crate::ws::token::{Token, Token::*};
+use crate::ws::syntax::convert;
+use crate::ws::token::{CountingSink, Token, Token::*, TokenSink};
 
 pub type RawInst = Inst<BitVec, BitVec>;
 
@@ -208,6 +210,111 @@ impl<I, L, E: Into<InstError>> From<E> for Inst<I, L> {
     }
 }
 
+impl RawInst {
+    /// Encodes the instruction as tokens into a sink.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the instruction is an `Error`.
+    pub fn encode<S: TokenSink + ?Sized>(&self, sink: &mut S) {
+        use Inst::*;
+        for &tok in self.opcode().tokens() {
+            sink.push(tok);
+        }
+        match self {
+            Push(arg) | Copy(arg) | Slide(arg) | Label(arg) | Call(arg) | Jmp(arg) | Jz(arg)
+            | Jn(arg) => {
+                for bit in arg.iter().by_vals() {
+                    sink.push(Token::from_bit(bit));
+                }
+                sink.push(L);
+            }
+            _ => {}
+        }
+    }
+
+    /// The number of tokens in the encoded instruction.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the instruction is an `Error`.
+    #[must_use]
+    pub fn token_len(&self) -> usize {
+        let mut sink = CountingSink::new();
+        self.encode(&mut sink);
+        sink.len()
+    }
+}
+
+impl RawInst {
+    /// The number of stack values that the instruction consumes and produces,
+    /// respectively.
+    ///
+    /// An instruction that reads a value without popping it counts it as both
+    /// consumed and produced, so `copy n` is `(n + 1, n + 2)` and `slide n` is
+    /// `(n + 1, 1)`. Arguments that are negative or too large to index the
+    /// stack are treated as `usize::MAX`, which no stack can satisfy. `shuffle`
+    /// permutes the whole stack, so it has no fixed effect and is `(0, 0)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the instruction is an `Error`.
+    #[must_use]
+    pub fn stack_effect(&self) -> (usize, usize) {
+        let index = |n: &BitVec| {
+            convert::integer_from_signed_bits(n)
+                .to_usize()
+                .unwrap_or(usize::MAX)
+        };
+        match self {
+            Inst::Push(_) => (0, 1),
+            Inst::Dup => (1, 2),
+            Inst::Copy(n) => {
+                let n = index(n);
+                (n.saturating_add(1), n.saturating_add(2))
+            }
+            Inst::Swap => (2, 2),
+            Inst::Slide(n) => (index(n).saturating_add(1), 1),
+            Inst::Add | Inst::Sub | Inst::Mul | Inst::Div | Inst::Mod => (2, 1),
+            Inst::Store => (2, 0),
+            Inst::Retrieve => (1, 1),
+            Inst::Drop
+            | Inst::Jz(_)
+            | Inst::Jn(_)
+            | Inst::Printc
+            | Inst::Printi
+            | Inst::Readc
+            | Inst::Readi => (1, 0),
+            Inst::Label(_)
+            | Inst::Call(_)
+            | Inst::Jmp(_)
+            | Inst::Ret
+            | Inst::End
+            | Inst::Shuffle
+            | Inst::DumpStack
+            | Inst::DumpHeap
+            | Inst::DumpTrace => (0, 0),
+            Inst::Error(_) => panic!("no stack effect for Error"),
+        }
+    }
+}
+
+impl Display for InstError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InstError::ParseError(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+impl Error for InstError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InstError::ParseError(err) => err.source(),
+        }
+    }
+}
+
 impl From<ParseError> for InstError {
     #[inline]
     fn from(err: ParseError) -> Self {
@@ -15,14 +15,22 @@
 //! - [The Whitespace Corpus](https://github.com/wspace/corpus)
 //! - [Esolang wiki](https://esolangs.org/wiki/Whitespace)
 
+pub use emit::emit_canonical;
+pub use error::WsError;
+pub use run::run_file;
 pub use token::Token;
 
 pub mod assembly;
+mod emit;
+mod error;
 pub mod gmh;
 pub mod inst;
 pub mod parse;
+pub mod program;
+mod run;
 pub mod syntax;
 pub mod token;
+pub mod vm;
 
 #[cfg(test)]
 mod tests;
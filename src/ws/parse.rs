@@ -6,36 +6,173 @@
 // later version. You should have received a copy of the GNU Lesser General
 // Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
 
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 use std::iter::FusedIterator;
 use std::sync::LazyLock;
 
-use bitvec::vec::BitVec;
+use bitvec::prelude::*;
 
-use crate::syntax::{PrefixError, PrefixTable, TokenSeq, Tokens};
+use crate::syntax::{PrefixEntry, PrefixError, PrefixTable, TokenSeq, Tokens, VariantIndex};
 use crate::text::EncodingError;
 use crate::ws::inst::{Inst, InstArg, Opcode, RawInst};
-use crate::ws::token::{Lexer, Token, TokenVec};
+use crate::ws::token::{BitUnpacker, Lexer, Token, TokenVec};
 
 /// Prefix table for parsing Whitespace opcodes.
-pub static TABLE: LazyLock<PrefixTable<Token, Opcode>> = LazyLock::new(|| {
+pub static TABLE: LazyLock<PrefixTable<Token, Opcode>> =
+    LazyLock::new(|| build_table(Opcode::iter().map(|opcode| (opcode.tokens(), opcode))).unwrap());
+
+/// An error from building a prefix table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TableError {
+    /// The tokens of `a` are a prefix of the tokens of `b`, or the reverse, so
+    /// parsing would be ambiguous.
+    PrefixConflict { a: Opcode, b: Opcode },
+}
+
+impl Display for TableError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::PrefixConflict { a, b } => write!(f, "prefix conflict between {a} and {b}"),
+        }
+    }
+}
+
+impl Error for TableError {}
+
+/// Builds a prefix table for parsing with [`Parser::with_table`], from the
+/// tokens of each opcode, which must be prefix-free.
+pub fn build_table<'a, I>(opcodes: I) -> Result<PrefixTable<Token, Opcode>, TableError>
+where
+    I: IntoIterator<Item = (&'a [Token], Opcode)>,
+{
     let mut table = PrefixTable::with_dense_width(3);
-    table.insert_all();
-    table
-});
+    for (toks, opcode) in opcodes {
+        table.insert(toks, opcode).map_err(|err| {
+            let opcodes = err.opcodes();
+            TableError::PrefixConflict {
+                a: opcodes[0],
+                b: opcodes[opcodes.len() - 1],
+            }
+        })?;
+    }
+    Ok(table)
+}
 
 #[derive(Clone, Debug)]
 pub struct Parser<'a, L> {
     table: &'a PrefixTable<Token, Opcode>,
     lex: L,
     partial: Option<PartialState>,
+    limits: ParseLimits,
+    allow_missing_final_terminator: bool,
+    count: usize,
+    halted: bool,
+}
+
+/// Limits on the size of parsed programs, to bound allocations when parsing
+/// untrusted input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ParseLimits {
+    /// The maximum number of tokens in an argument, excluding its terminating
+    /// `L`.
+    pub max_operand_tokens: usize,
+    /// The maximum number of instructions in a program.
+    pub max_instructions: usize,
+}
+
+impl Default for ParseLimits {
+    #[inline]
+    fn default() -> Self {
+        ParseLimits {
+            max_operand_tokens: 1 << 20,
+            max_instructions: 1 << 24,
+        }
+    }
+}
+
+/// A limit from [`ParseLimits`], that was exceeded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Limit {
+    /// An argument to the opcode exceeded `max_operand_tokens`.
+    OperandTokens(Opcode),
+    /// The program exceeded `max_instructions`.
+    Instructions,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum ParseError {
+    /// The lexer failed, after the tokens of a partial instruction.
     EncodingError(EncodingError, Vec<Token>),
+    /// The tokens do not start any opcode, as in corrupt files.
     UnknownOpcode(TokenSeq<Token>),
+    /// EOF was reached in an opcode, which is a prefix of the listed opcodes.
     IncompleteInst(TokenSeq<Token>, Vec<Opcode>),
+    /// EOF was reached in an argument, before its terminating `L`, as in
+    /// truncated files.
     UnterminatedArg(Opcode, BitVec),
+    /// A limit on the size of the program was exceeded. Parsing stops after
+    /// this error.
+    LimitExceeded(Limit),
+}
+
+impl ParseError {
+    /// The tokens of the partial instruction, that were read before the
+    /// error.
+    #[must_use]
+    pub fn tokens(&self) -> Vec<Token> {
+        match self {
+            ParseError::EncodingError(_, toks) => toks.clone(),
+            ParseError::UnknownOpcode(seq) | ParseError::IncompleteInst(seq, _) => (*seq).into(),
+            ParseError::UnterminatedArg(opcode, bits) => {
+                let mut toks = Vec::from(opcode.tokens());
+                toks.append_bits(bits);
+                toks
+            }
+            ParseError::LimitExceeded(Limit::OperandTokens(opcode)) => Vec::from(opcode.tokens()),
+            ParseError::LimitExceeded(Limit::Instructions) => Vec::new(),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let toks = self
+            .tokens()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<String>();
+        match self {
+            ParseError::EncodingError(err, _) => write!(f, "{err} after `{toks}`"),
+            ParseError::UnknownOpcode(_) => write!(f, "unknown opcode `{toks}`"),
+            ParseError::IncompleteInst(_, opcodes) => {
+                write!(f, "incomplete opcode `{toks}` at EOF; expected ")?;
+                for (i, opcode) in opcodes.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    write!(f, "{sep}{opcode}")?;
+                }
+                Ok(())
+            }
+            ParseError::UnterminatedArg(opcode, _) => {
+                write!(f, "unterminated argument to {opcode} at EOF: `{toks}`")
+            }
+            ParseError::LimitExceeded(Limit::OperandTokens(opcode)) => {
+                write!(f, "argument to {opcode} exceeds the maximum length")
+            }
+            ParseError::LimitExceeded(Limit::Instructions) => {
+                write!(f, "program exceeds the maximum number of instructions")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::EncodingError(err, _) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -48,19 +185,66 @@ impl<L: Lexer> Parser<'static, L> {
     #[inline]
     #[must_use]
     pub fn new(lex: L) -> Self {
+        Parser::with_table(&TABLE, lex)
+    }
+}
+
+impl<'a, L: Lexer> Parser<'a, L> {
+    #[inline]
+    #[must_use]
+    pub fn with_table(table: &'a PrefixTable<Token, Opcode>, lex: L) -> Self {
         Parser {
-            table: &TABLE,
+            table,
             lex,
             partial: None,
+            limits: ParseLimits::default(),
+            allow_missing_final_terminator: false,
+            count: 0,
+            halted: false,
         }
     }
-}
 
-impl<'a, L: Lexer> Parser<'a, L> {
+    /// Sets the limits on the size of the program, which default to
+    /// [`ParseLimits::default`].
     #[inline]
     #[must_use]
-    pub fn with_table(table: &'a PrefixTable<Token, Opcode>, lex: L) -> Self {
-        Parser { table, lex, partial: None }
+    pub fn limits(mut self, limits: ParseLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets whether EOF is treated as the `L` that terminates the last
+    /// instruction, when that completes it, for programs from editors that
+    /// strip the trailing newline. This is off by default, so such programs
+    /// are errors.
+    ///
+    /// Only a single `L` is implied, which ends either the argument of the last
+    /// instruction or an opcode that takes no argument, like `end`.
+    #[inline]
+    #[must_use]
+    pub fn allow_missing_final_terminator(mut self, allow: bool) -> Self {
+        self.allow_missing_final_terminator = allow;
+        self
+    }
+
+    /// Completes an opcode that was cut off by EOF with an implicit `L`, if it
+    /// takes no argument.
+    fn complete_at_eof(&self, mut seq: TokenSeq<Token>) -> Option<Opcode> {
+        seq.try_push(&Token::L).ok()?;
+        match self.table.get(seq) {
+            Some(&PrefixEntry::Terminal(opcode)) => match Inst::from(opcode) {
+                Inst::Push(())
+                | Inst::Copy(())
+                | Inst::Slide(())
+                | Inst::Label(())
+                | Inst::Call(())
+                | Inst::Jmp(())
+                | Inst::Jz(())
+                | Inst::Jn(()) => None,
+                _ => Some(opcode),
+            },
+            _ => None,
+        }
     }
 
     fn parse_arg(&mut self, opcode: Opcode, partial: Option<BitVec>) -> RawInst {
@@ -68,15 +252,21 @@ impl<'a, L: Lexer> Parser<'a, L> {
             let mut bits = partial.unwrap_or_else(|| BitVec::with_capacity(64));
             loop {
                 match self.lex.next() {
-                    Some(Ok(Token::S)) => bits.push(false),
-                    Some(Ok(Token::T)) => bits.push(true),
-                    Some(Ok(Token::L)) => break,
+                    Some(Ok(tok)) => match tok.as_bit() {
+                        Some(_) if bits.len() >= self.limits.max_operand_tokens => {
+                            self.halted = true;
+                            return Err(ParseError::LimitExceeded(Limit::OperandTokens(opcode)));
+                        }
+                        Some(bit) => bits.push(bit),
+                        None => break,
+                    },
                     Some(Err(err)) => {
                         let mut toks = Vec::from(opcode.tokens());
                         toks.append_bits(&bits);
                         self.partial = Some(PartialState::ParsingArg(opcode, bits));
                         return Err(ParseError::EncodingError(err, toks));
                     }
+                    None if self.allow_missing_final_terminator => break,
                     None => return Err(ParseError::UnterminatedArg(opcode, bits)),
                 }
             }
@@ -92,6 +282,9 @@ impl<L: Lexer> Iterator for Parser<'_, L> {
     type Item = RawInst;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.halted {
+            return None;
+        }
         // Restore state, if an instruction was interrupted with a lex error
         // after being partially parsed.
         let partial_seq = match self.partial.take() {
@@ -101,20 +294,77 @@ impl<L: Lexer> Iterator for Parser<'_, L> {
             }
             None => TokenSeq::new(),
         };
-        match self.table.parse_at(&mut self.lex, partial_seq)? {
-            Ok(opcode) => Some(self.parse_arg(opcode, None)),
+        let inst = match self.table.parse_at(&mut self.lex, partial_seq)? {
+            Ok(opcode) => self.parse_arg(opcode, None),
+            Err(PrefixError::IncompleteOpcode(seq, prefix)) => {
+                match (self.allow_missing_final_terminator)
+                    .then(|| self.complete_at_eof(seq))
+                    .flatten()
+                {
+                    Some(opcode) => self.parse_arg(opcode, None),
+                    None => Inst::from(ParseError::IncompleteInst(seq, prefix)),
+                }
+            }
             Err(err) => {
                 if let PrefixError::EncodingError(_, seq) = err {
                     self.partial = Some(PartialState::ParsingOpcode(seq));
                 }
-                Some(Inst::from(ParseError::from(err)))
+                Inst::from(ParseError::from(err))
             }
+        };
+        if self.count >= self.limits.max_instructions {
+            self.halted = true;
+            return Some(Inst::from(ParseError::LimitExceeded(Limit::Instructions)));
         }
+        self.count += 1;
+        Some(inst)
     }
 }
 
 impl<L: Lexer + FusedIterator> FusedIterator for Parser<'_, L> {}
 
+/// Parses instructions directly from tokens packed in a compact bitwise
+/// encoding, without unpacking them first.
+///
+/// See [`bit_pack`](crate::ws::token::bit_pack) for a description of the
+/// encoding.
+#[must_use]
+pub fn parse_packed<T: BitStore, O: BitOrder>(bits: &BitSlice<T, O>) -> Vec<RawInst> {
+    Parser::new(BitUnpacker::new(bits).map(Ok)).collect()
+}
+
+/// Parses instructions directly from tokens packed in a compact bitwise
+/// encoding, padded with trailing bits, as by
+/// [`bit_pack_padded`](crate::ws::token::bit_pack_padded).
+#[must_use]
+pub fn parse_packed_padded<T: BitStore, O: BitOrder>(bits: &[T]) -> Vec<RawInst> {
+    Parser::new(BitUnpacker::<T, O>::new_padded(bits).map(Ok)).collect()
+}
+
+/// Parses instructions until the first that fails to parse, and returns the
+/// parsed instructions and the number of tokens that they span, so that
+/// trailing data after a program can be located.
+pub fn parse_prefix<L: Lexer>(lex: L) -> (Vec<RawInst>, usize) {
+    let mut insts = Vec::new();
+    let mut len = 0;
+    for inst in Parser::new(lex) {
+        if let Inst::Error(_) = inst {
+            break;
+        }
+        len += inst.token_len();
+        insts.push(inst);
+    }
+    (insts, len)
+}
+
+/// Parses at most `n` instructions and stops, without reading tokens past
+/// the last, so that the start of a large program can be parsed quickly, as
+/// for feedback in an editor. Errors are included as [`Inst::Error`] and
+/// count towards `n`.
+pub fn parse_take<L: Lexer>(lex: L, n: usize) -> Vec<RawInst> {
+    Parser::new(lex).take(n).collect()
+}
+
 impl From<PrefixError<Token, Opcode>> for ParseError {
     fn from(err: PrefixError<Token, Opcode>) -> Self {
         match err {
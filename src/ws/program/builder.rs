@@ -0,0 +1,171 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use bitvec::slice::BitSlice;
+use bitvec::vec::BitVec;
+use rug::Integer;
+
+use crate::ws::inst::{Inst, RawInst};
+use crate::ws::program::LabelGen;
+use crate::ws::syntax::{convert, Sign};
+
+/// A builder for constructing programs, for code generation.
+///
+/// Fresh labels from [`ProgramBuilder::fresh_label`] never collide with labels
+/// that were passed to the builder before.
+#[derive(Clone, Debug, Default)]
+pub struct ProgramBuilder {
+    insts: Vec<RawInst>,
+    labels: LabelGen,
+}
+
+macro_rules! simple_insts(($($method:ident => $opcode:ident),+ $(,)?) => {
+    $(
+        #[doc = concat!("Appends `", stringify!($opcode), "`.")]
+        #[inline]
+        pub fn $method(&mut self) -> &mut Self {
+            self.inst(Inst::$opcode)
+        }
+    )+
+});
+
+macro_rules! label_insts(($($method:ident => $opcode:ident),+ $(,)?) => {
+    $(
+        #[doc = concat!("Appends `", stringify!($opcode), "` with a label.")]
+        #[inline]
+        pub fn $method(&mut self, label: &BitSlice) -> &mut Self {
+            self.labels.avoid(label.to_bitvec());
+            self.inst(Inst::$opcode(label.to_bitvec()))
+        }
+    )+
+});
+
+impl ProgramBuilder {
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        ProgramBuilder::default()
+    }
+
+    /// Constructs a builder with space for at least `capacity` instructions.
+    #[inline]
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        ProgramBuilder {
+            insts: Vec::with_capacity(capacity),
+            labels: LabelGen::new(),
+        }
+    }
+
+    /// Generates a label, that is unused by the program so far.
+    #[inline]
+    #[must_use]
+    pub fn fresh_label(&mut self) -> BitVec {
+        self.labels.next_label()
+    }
+
+    /// Appends an instruction.
+    #[inline]
+    pub fn inst(&mut self, inst: RawInst) -> &mut Self {
+        self.insts.push(inst);
+        self
+    }
+
+    /// Appends `push` with an integer.
+    pub fn push<N: Into<Integer>>(&mut self, n: N) -> &mut Self {
+        self.inst(Inst::Push(Self::int_bits(&n.into())))
+    }
+
+    /// Appends `copy` with an index.
+    pub fn copy<N: Into<Integer>>(&mut self, n: N) -> &mut Self {
+        self.inst(Inst::Copy(Self::int_bits(&n.into())))
+    }
+
+    /// Appends `slide` with a count.
+    pub fn slide<N: Into<Integer>>(&mut self, n: N) -> &mut Self {
+        self.inst(Inst::Slide(Self::int_bits(&n.into())))
+    }
+
+    simple_insts! {
+        dup => Dup,
+        swap => Swap,
+        drop => Drop,
+        add => Add,
+        sub => Sub,
+        mul => Mul,
+        div => Div,
+        modulo => Mod,
+        store => Store,
+        retrieve => Retrieve,
+        ret => Ret,
+        end => End,
+        printc => Printc,
+        printi => Printi,
+        readc => Readc,
+        readi => Readi,
+    }
+
+    label_insts! {
+        label => Label,
+        call => Call,
+        jmp => Jmp,
+        jz => Jz,
+        jn => Jn,
+    }
+
+    /// The instructions appended so far.
+    #[inline]
+    #[must_use]
+    pub fn insts(&self) -> &[RawInst] {
+        &self.insts
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn build(self) -> Vec<RawInst> {
+        self.insts
+    }
+
+    fn int_bits(n: &Integer) -> BitVec {
+        let sign = if *n < 0 { Sign::Neg } else { Sign::Pos };
+        convert::signed_bits_from_integer(n, sign, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+    use crate::ws::vm::Vm;
+
+    #[test]
+    fn countdown() {
+        let mut b = ProgramBuilder::with_capacity(16);
+        b.label(bits![1]);
+        let (head, exit) = (b.fresh_label(), b.fresh_label());
+        assert_eq!((bitvec![], bitvec![0]), (head.clone(), exit.clone()));
+        b.push(3)
+            .label(&head)
+            .dup()
+            .jz(&exit)
+            .dup()
+            .printi()
+            .push(1)
+            .sub()
+            .jmp(&head)
+            .label(&exit)
+            .push(-1)
+            .printi()
+            .end();
+        assert_eq!(bitvec![0, 0], b.fresh_label());
+        assert_eq!(Inst::Push(bitvec![1, 1]), b.insts()[b.insts().len() - 3]);
+        let output = Vm::new(b.build()).run_capture(b"").unwrap();
+        assert_eq!(b"321-1", &output[..]);
+    }
+}
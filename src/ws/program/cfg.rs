@@ -0,0 +1,219 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+use bitvec::vec::BitVec;
+
+use crate::ws::assembly::disassemble;
+use crate::ws::inst::{Inst, RawInst};
+
+/// The control-flow graph of a program, as built by [`build_cfg`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Cfg<'a> {
+    insts: &'a [RawInst],
+    blocks: Vec<BasicBlock>,
+}
+
+/// A maximal straight-line sequence of instructions, which is only entered at
+/// its first instruction and only left after its last.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BasicBlock {
+    /// The range of the instructions in the block.
+    pub range: Range<usize>,
+    /// The blocks that control can transfer to from this block.
+    pub succs: Vec<Edge>,
+}
+
+/// An edge to a successor block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Edge {
+    /// The index of the successor block.
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// The way that control transfers along an [`Edge`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// Control falls through to the next instruction.
+    Fallthrough,
+    /// An unconditional `jmp`.
+    Jump,
+    /// A conditional `jz` or `jn`, when the branch is taken.
+    Branch,
+    /// A `call`. The caller block also falls through, for when the callee
+    /// returns.
+    Call,
+}
+
+impl<'a> Cfg<'a> {
+    /// The basic blocks, in program order.
+    #[inline]
+    #[must_use]
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    /// The instructions of a block.
+    #[inline]
+    #[must_use]
+    pub fn block_insts(&self, block: usize) -> &'a [RawInst] {
+        &self.insts[self.blocks[block].range.clone()]
+    }
+
+    /// Renders the graph as a Graphviz DOT digraph, with one node per block,
+    /// labeled with its index range and disassembly. Jumps and calls are drawn
+    /// bold and taken branches dashed.
+    #[must_use]
+    pub fn to_dot(&self) -> String {
+        let mut s = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        for (i, block) in self.blocks.iter().enumerate() {
+            let Range { start, end } = block.range;
+            let label = format!("[{start}, {end})\n{}", disassemble(self.block_insts(i)));
+            writeln!(s, "    b{i} [label=\"{}\"];", escape_dot(&label)).unwrap();
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            for edge in &block.succs {
+                let attrs = match edge.kind {
+                    EdgeKind::Fallthrough => "",
+                    EdgeKind::Jump => " [style=bold]",
+                    EdgeKind::Branch => " [style=dashed, label=\"taken\"]",
+                    EdgeKind::Call => " [style=bold, label=\"call\"]",
+                };
+                writeln!(s, "    b{i} -> b{}{attrs};", edge.to).unwrap();
+            }
+        }
+        s.push_str("}\n");
+        s
+    }
+}
+
+/// Escapes a string for a quoted DOT label, with lines left-justified.
+fn escape_dot(label: &str) -> String {
+    let mut s = String::with_capacity(label.len());
+    for ch in label.chars() {
+        match ch {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\l"),
+            _ => s.push(ch),
+        }
+    }
+    if !s.ends_with("\\l") {
+        s.push_str("\\l");
+    }
+    s
+}
+
+/// Splits a program into basic blocks, which start at each label and after
+/// each transfer of control. Labels resolve to their first definition, like
+/// the interpreter, and transfers to undefined labels have no edge.
+#[must_use]
+pub fn build_cfg(insts: &[RawInst]) -> Cfg<'_> {
+    let mut starts = Vec::new();
+    for (i, inst) in insts.iter().enumerate() {
+        let leader = i == 0 || matches!(inst, Inst::Label(_)) || ends_block(&insts[i - 1]);
+        if leader {
+            starts.push(i);
+        }
+    }
+    let block_of = |pc: usize| starts.partition_point(|&start| start <= pc) - 1;
+    let mut targets: HashMap<&BitVec, usize> = HashMap::new();
+    for (i, inst) in insts.iter().enumerate() {
+        if let Inst::Label(l) = inst {
+            targets.entry(l).or_insert_with(|| block_of(i));
+        }
+    }
+
+    let blocks = (starts.iter().enumerate())
+        .map(|(b, &start)| {
+            let end = starts.get(b + 1).copied().unwrap_or(insts.len());
+            let next = (end < insts.len()).then_some(b + 1);
+            let mut succs = Vec::new();
+            let mut push = |to: Option<usize>, kind| {
+                if let Some(to) = to {
+                    succs.push(Edge { to, kind });
+                }
+            };
+            match &insts[end - 1] {
+                Inst::Jmp(l) => push(targets.get(l).copied(), EdgeKind::Jump),
+                Inst::Jz(l) | Inst::Jn(l) => {
+                    push(targets.get(l).copied(), EdgeKind::Branch);
+                    push(next, EdgeKind::Fallthrough);
+                }
+                Inst::Call(l) => {
+                    push(targets.get(l).copied(), EdgeKind::Call);
+                    push(next, EdgeKind::Fallthrough);
+                }
+                Inst::Ret | Inst::End | Inst::Error(_) => {}
+                _ => push(next, EdgeKind::Fallthrough),
+            }
+            BasicBlock { range: start..end, succs }
+        })
+        .collect();
+    Cfg { insts, blocks }
+}
+
+fn ends_block(inst: &RawInst) -> bool {
+    matches!(
+        inst,
+        Inst::Call(_)
+            | Inst::Jmp(_)
+            | Inst::Jz(_)
+            | Inst::Jn(_)
+            | Inst::Ret
+            | Inst::End
+            | Inst::Error(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn to_dot() {
+        let insts = vec![
+            Inst::Push(bitvec![0, 1]),
+            Inst::Label(bitvec![1]),
+            Inst::Dup,
+            Inst::Jz(bitvec![0, 1]),
+            Inst::Jmp(bitvec![1]),
+            Inst::Label(bitvec![0, 1]),
+            Inst::End,
+        ];
+        let cfg = build_cfg(&insts);
+        let ranges = cfg.blocks().iter().map(|b| b.range.clone());
+        assert_eq!(vec![0..1, 1..4, 4..5, 5..7], ranges.collect::<Vec<_>>());
+        let taken = Edge { to: 3, kind: EdgeKind::Branch };
+        let not_taken = Edge {
+            to: 2,
+            kind: EdgeKind::Fallthrough,
+        };
+        assert_eq!(vec![taken, not_taken], cfg.blocks()[1].succs);
+        let expected = "digraph cfg {
+    node [shape=box, fontname=monospace];
+    b0 [label=\"[0, 1)\\l    push 1\\l\"];
+    b1 [label=\"[1, 4)\\l1:\\l    dup\\l    jz .01\\l\"];
+    b2 [label=\"[4, 5)\\l    jmp 1\\l\"];
+    b3 [label=\"[5, 7)\\l.01:\\l    end\\l\"];
+    b0 -> b1;
+    b1 -> b3 [style=dashed, label=\"taken\"];
+    b1 -> b2;
+    b2 -> b1 [style=bold];
+}
+";
+        assert_eq!(expected, cfg.to_dot());
+        assert_eq!("a\\\"b\\\\\\l", escape_dot("a\"b\\\n"));
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use crate::ws::inst::RawInst;
+use crate::ws::vm::{StepResult, Vm};
+
+/// How an execution finished.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RunStatus {
+    /// The program executed `end`.
+    Halted,
+    /// The program trapped with a runtime error.
+    Trapped,
+    /// The program was still running when the step limit was reached.
+    StepLimit,
+}
+
+/// A difference between the executions of two programs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TraceMismatch {
+    /// The outputs differ, first at byte `offset`.
+    Output { offset: usize },
+    /// The outputs are equal, but the programs finished differently.
+    Status { a: RunStatus, b: RunStatus },
+}
+
+/// Runs both programs on the same input and reports whether they produce
+/// the same output and finish the same way. Each is stopped after
+/// `max_steps` instructions, so that non-terminating programs can be
+/// compared.
+///
+/// This is a test of equivalence on one input, so is useful for checking
+/// program transformations, like optimization, against a set of inputs.
+#[must_use]
+pub fn traces_equal(a: &[RawInst], b: &[RawInst], input: &[u8], max_steps: u64) -> bool {
+    compare_traces(a, b, input, max_steps).is_ok()
+}
+
+/// Like [`traces_equal`], but reports where the executions differ.
+pub fn compare_traces(
+    a: &[RawInst],
+    b: &[RawInst],
+    input: &[u8],
+    max_steps: u64,
+) -> Result<(), TraceMismatch> {
+    let (out_a, status_a) = trace(a, input, max_steps);
+    let (out_b, status_b) = trace(b, input, max_steps);
+    if out_a != out_b {
+        let offset = (out_a.iter().zip(&out_b))
+            .position(|(x, y)| x != y)
+            .unwrap_or_else(|| out_a.len().min(out_b.len()));
+        return Err(TraceMismatch::Output { offset });
+    }
+    if status_a != status_b {
+        return Err(TraceMismatch::Status { a: status_a, b: status_b });
+    }
+    Ok(())
+}
+
+fn trace(insts: &[RawInst], mut input: &[u8], max_steps: u64) -> (Vec<u8>, RunStatus) {
+    let mut vm = Vm::new(insts.to_vec());
+    let mut output = Vec::new();
+    for _ in 0..max_steps {
+        match vm.step(&mut input, &mut output) {
+            Ok(StepResult::Continue | StepResult::Breakpoint) => {}
+            Ok(StepResult::Halted) => return (output, RunStatus::Halted),
+            Err(_) => return (output, RunStatus::Trapped),
+        }
+    }
+    (output, RunStatus::StepLimit)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+    use crate::ws::inst::Inst;
+
+    #[test]
+    fn compare() {
+        let print = |n: BitVec| vec![Inst::Push(n), Inst::Printi, Inst::End];
+        let a = print(bitvec![0, 1, 0, 1]);
+        assert!(traces_equal(&a, &a, b"", 100));
+        assert_eq!(
+            Err(TraceMismatch::Output { offset: 0 }),
+            compare_traces(&a, &print(bitvec![0, 1, 1, 0]), b"", 100),
+        );
+
+        let mut trapped = a.clone();
+        trapped.pop();
+        assert_eq!(
+            Err(TraceMismatch::Status {
+                a: RunStatus::Halted,
+                b: RunStatus::Trapped,
+            }),
+            compare_traces(&a, &trapped, b"", 100),
+        );
+
+        let looping = vec![Inst::Label(bitvec![1]), Inst::Jmp(bitvec![1])];
+        assert!(traces_equal(&looping, &looping, b"", 100));
+        assert_eq!(
+            Err(TraceMismatch::Output { offset: 0 }),
+            compare_traces(&looping, &a, b"", 100),
+        );
+    }
+}
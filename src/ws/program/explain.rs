@@ -0,0 +1,58 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use crate::ws::assembly::label_text;
+use crate::ws::inst::{Inst, RawInst};
+use crate::ws::syntax::convert;
+
+/// Describes each instruction in plain English, with its argument decoded, for
+/// stepping through a program while learning Whitespace.
+///
+/// Labels are written as in the assembly format.
+#[must_use]
+pub fn explain(insts: &[RawInst]) -> Vec<String> {
+    insts.iter().map(explain_inst).collect()
+}
+
+fn explain_inst(inst: &RawInst) -> String {
+    let value = |n| convert::integer_from_signed_bits(n);
+    match inst {
+        Inst::Push(n) => format!("push {} onto the stack", value(n)),
+        Inst::Dup => "duplicate the top of the stack".to_owned(),
+        Inst::Copy(n) => format!("copy the value {} below the top onto the stack", value(n)),
+        Inst::Swap => "swap the top two values on the stack".to_owned(),
+        Inst::Drop => "discard the top of the stack".to_owned(),
+        Inst::Slide(n) => format!("discard {} values below the top of the stack", value(n)),
+        Inst::Add => "pop two values and push their sum".to_owned(),
+        Inst::Sub => "pop two values and push their difference".to_owned(),
+        Inst::Mul => "pop two values and push their product".to_owned(),
+        Inst::Div => "pop two values and push their quotient".to_owned(),
+        Inst::Mod => "pop two values and push their remainder".to_owned(),
+        Inst::Store => "pop a value and an address, and store the value in the heap".to_owned(),
+        Inst::Retrieve => "pop an address and push the value in the heap there".to_owned(),
+        Inst::Label(l) => format!("mark label {}", label_text(l)),
+        Inst::Call(l) => format!("call the subroutine at label {}", label_text(l)),
+        Inst::Jmp(l) => format!("jump to label {}", label_text(l)),
+        Inst::Jz(l) => format!("pop a value and, if zero, jump to label {}", label_text(l)),
+        Inst::Jn(l) => format!(
+            "pop a value and, if negative, jump to label {}",
+            label_text(l)
+        ),
+        Inst::Ret => "return from the subroutine".to_owned(),
+        Inst::End => "end the program".to_owned(),
+        Inst::Printc => "pop a value and print it as a character".to_owned(),
+        Inst::Printi => "pop a value and print it as an integer".to_owned(),
+        Inst::Readc => "pop an address and read a character into the heap there".to_owned(),
+        Inst::Readi => "pop an address and read an integer into the heap there".to_owned(),
+        Inst::Shuffle => "shuffle the stack randomly".to_owned(),
+        Inst::DumpStack => "print the stack, for debugging".to_owned(),
+        Inst::DumpHeap => "print the heap, for debugging".to_owned(),
+        Inst::DumpTrace => "print a stack trace, for debugging".to_owned(),
+        Inst::Error(err) => format!("invalid instruction: {err}"),
+    }
+}
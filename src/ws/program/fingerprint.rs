@@ -0,0 +1,139 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use bitvec::slice::BitSlice;
+
+use crate::ws::inst::{Inst, InstError, RawInst};
+use crate::ws::program::intern;
+use crate::ws::syntax::convert;
+
+/// Hashes a program in a canonical form, for deduplicating and caching
+/// programs, so that programs which differ only in the bits of their labels or
+/// in the encoding of their integers hash equally.
+///
+/// In the canonical form, labels are numbered in order of first definition or
+/// use, as by [`intern`], and integers are hashed by their value under the
+/// standard sign-magnitude encoding, so leading zeros and the sign of zero are
+/// ignored. For bit-exact identity, use
+/// [`fingerprint_exact`].
+///
+/// The hash is FNV-1a over the opcodes and arguments, so it does not depend on
+/// the platform, unlike [`std::hash::Hash`], and can be persisted.
+#[must_use]
+pub fn fingerprint(insts: &[RawInst]) -> u64 {
+    let mut h = Fnv1a::new();
+    for inst in intern(insts).insts() {
+        match inst {
+            Inst::Push(n) | Inst::Copy(n) | Inst::Slide(n) => {
+                h.write(&[inst.opcode() as u8]);
+                let n = convert::integer_from_signed_bits(n);
+                h.write(n.to_string_radix(16).as_bytes());
+                h.write(b"\n");
+            }
+            Inst::Label(l) | Inst::Call(l) | Inst::Jmp(l) | Inst::Jz(l) | Inst::Jn(l) => {
+                h.write(&[inst.opcode() as u8]);
+                h.write(&l.0.to_le_bytes());
+            }
+            Inst::Error(err) => write_error(&mut h, err),
+            _ => h.write(&[inst.opcode() as u8]),
+        }
+    }
+    h.finish()
+}
+
+/// Hashes a program exactly as it is encoded, so that programs hash equally
+/// only when they have the same tokens, barring collisions. See
+/// [`fingerprint`] for the canonical form.
+#[must_use]
+pub fn fingerprint_exact(insts: &[RawInst]) -> u64 {
+    let mut h = Fnv1a::new();
+    for inst in insts {
+        match inst {
+            Inst::Push(arg)
+            | Inst::Copy(arg)
+            | Inst::Slide(arg)
+            | Inst::Label(arg)
+            | Inst::Call(arg)
+            | Inst::Jmp(arg)
+            | Inst::Jz(arg)
+            | Inst::Jn(arg) => {
+                h.write(&[inst.opcode() as u8]);
+                write_bits(&mut h, arg);
+            }
+            Inst::Error(err) => write_error(&mut h, err),
+            _ => h.write(&[inst.opcode() as u8]),
+        }
+    }
+    h.finish()
+}
+
+fn write_bits(h: &mut Fnv1a, bits: &BitSlice) {
+    h.write(&(bits.len() as u64).to_le_bytes());
+    for chunk in bits.chunks(8) {
+        let byte = (chunk.iter().by_vals()).fold(0, |byte, bit| byte << 1 | u8::from(bit));
+        h.write(&[byte]);
+    }
+}
+
+fn write_error(h: &mut Fnv1a, err: &InstError) {
+    h.write(&[u8::MAX]);
+    h.write(format!("{err:?}").as_bytes());
+}
+
+/// The 64-bit FNV-1a hash.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    #[inline]
+    fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn canonical() {
+        let prog = |label: BitVec, n: BitVec| {
+            vec![
+                Inst::Label(label.clone()),
+                Inst::Push(n),
+                Inst::Printi,
+                Inst::Jmp(label),
+            ]
+        };
+        let a = prog(bitvec![1], bitvec![0, 1]);
+        let b = prog(bitvec![0, 0, 1], bitvec![0, 0, 0, 1]);
+        assert_eq!(fingerprint(&a), fingerprint(&b));
+        assert_ne!(fingerprint_exact(&a), fingerprint_exact(&b));
+        assert_eq!(fingerprint_exact(&a), fingerprint_exact(&a.clone()));
+
+        let c = prog(bitvec![1], bitvec![1, 1]);
+        assert_ne!(fingerprint(&a), fingerprint(&c));
+        assert_ne!(fingerprint(&a), fingerprint(&a[..3]));
+        // The sign of zero is not significant.
+        let (pos, neg) = (Inst::Push(bitvec![0]), Inst::Push(bitvec![1]));
+        assert_eq!(fingerprint(&[pos]), fingerprint(&[neg]));
+    }
+}
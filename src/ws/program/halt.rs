@@ -0,0 +1,100 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::collections::HashSet;
+use std::io;
+
+use rug::Integer;
+
+use crate::ws::inst::RawInst;
+use crate::ws::program::uses_heap;
+use crate::ws::vm::{StepResult, Vm};
+
+/// Whether a program halts, as determined by [`try_prove_halts`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HaltProof {
+    /// The program executes `end` or traps.
+    Halts,
+    /// The program reaches a state that it was in before, so runs forever.
+    Loops,
+    /// The program uses the heap or input, or does not halt or repeat a state
+    /// within the bound.
+    Unknown,
+}
+
+/// Tries to prove whether a program halts, by exploring the states that it
+/// reaches, up to `max_states` of them.
+///
+/// Only programs which use just the stack are analyzed, that is, those
+/// without `store`, `retrieve`, `readc`, or `readi`. Such a program is fully
+/// determined, since output does not affect execution, so its state is the
+/// program counter, the stack, and the call stack. When a state repeats, the
+/// program loops. Programs whose stack or call stack grows without bound
+/// never repeat a state, so are `Unknown`.
+#[must_use]
+pub fn try_prove_halts(insts: &[RawInst], max_states: usize) -> HaltProof {
+    if uses_heap(insts) {
+        return HaltProof::Unknown;
+    }
+    let mut vm = Vm::new(insts.to_vec());
+    let mut seen: HashSet<(usize, Vec<Integer>, Vec<usize>)> = HashSet::new();
+    while seen.len() < max_states {
+        let state = (vm.pc(), vm.stack().to_vec(), vm.call_stack().to_vec());
+        if !seen.insert(state) {
+            return HaltProof::Loops;
+        }
+        match vm.step(&mut io::empty(), &mut io::sink()) {
+            Ok(StepResult::Continue | StepResult::Breakpoint) => {}
+            Ok(StepResult::Halted) | Err(_) => return HaltProof::Halts,
+        }
+    }
+    HaltProof::Unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+    use crate::ws::inst::Inst;
+
+    #[test]
+    fn prove_halts() {
+        // Counts down from 3, then ends.
+        let countdown = vec![
+            Inst::Push(bitvec![0, 1, 1]),
+            Inst::Label(bitvec![1]),
+            Inst::Dup,
+            Inst::Printi,
+            Inst::Push(bitvec![0, 1]),
+            Inst::Sub,
+            Inst::Dup,
+            Inst::Jz(bitvec![0]),
+            Inst::Jmp(bitvec![1]),
+            Inst::Label(bitvec![0]),
+            Inst::End,
+        ];
+        assert_eq!(HaltProof::Halts, try_prove_halts(&countdown, 100));
+        assert_eq!(HaltProof::Unknown, try_prove_halts(&countdown, 10));
+
+        let spin = vec![Inst::Label(bitvec![1]), Inst::Jmp(bitvec![1])];
+        assert_eq!(HaltProof::Loops, try_prove_halts(&spin, 100));
+        // The stack grows forever, so no state repeats.
+        let grow = vec![
+            Inst::Label(bitvec![1]),
+            Inst::Push(bitvec![0, 1]),
+            Inst::Jmp(bitvec![1]),
+        ];
+        assert_eq!(HaltProof::Unknown, try_prove_halts(&grow, 1000));
+        // A trap halts.
+        assert_eq!(HaltProof::Halts, try_prove_halts(&[Inst::Drop], 100));
+
+        let store = vec![Inst::Push(bitvec![0]), Inst::Dup, Inst::Store, Inst::End];
+        assert_eq!(HaltProof::Unknown, try_prove_halts(&store, 100));
+    }
+}
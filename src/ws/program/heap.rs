@@ -0,0 +1,326 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::collections::{BTreeSet, HashMap};
+
+use rug::Integer;
+
+use crate::ws::inst::{Inst, RawInst};
+use crate::ws::syntax::convert;
+
+/// Heap addresses accessed by a program, as determined statically.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct HeapSummary {
+    /// Constant addresses written by `store`, `readc`, or `readi`.
+    pub stored: BTreeSet<Integer>,
+    /// Constant addresses read by `retrieve`.
+    pub retrieved: BTreeSet<Integer>,
+    /// Whether any address is computed at runtime, in which case the program
+    /// may access addresses other than those listed.
+    pub computed: bool,
+}
+
+/// Summarizes the heap addresses accessed by a program.
+///
+/// Constants are tracked through stack manipulation and `add`, `sub`, and
+/// `mul` within straight-line code. Values are forgotten at labels and after
+/// calls, since control may arrive there from elsewhere.
+#[must_use]
+pub fn heap_access_summary(insts: &[RawInst]) -> HeapSummary {
+    let mut summary = HeapSummary::default();
+    let mut stack = ConstStack::default();
+    for inst in insts {
+        if let Some(access) = stack.exec(inst) {
+            summary.record(access.addr, access.store);
+        }
+    }
+    summary
+}
+
+/// Whether a program may access the heap, that is, whether it has any
+/// `store`, `retrieve`, `readc`, or `readi`. This is a cheap syntactic check,
+/// unlike [`heap_access_summary`].
+#[must_use]
+pub fn uses_heap(insts: &[RawInst]) -> bool {
+    (insts.iter()).any(|inst| {
+        matches!(
+            inst,
+            Inst::Store | Inst::Retrieve | Inst::Readc | Inst::Readi
+        )
+    })
+}
+
+/// Finds `store` instructions to a constant address, which are overwritten
+/// by another `store` to that address within the same basic block, before it
+/// could be read by `retrieve` or `dump_heap`. Returns their indices, in
+/// order.
+///
+/// Addresses are tracked as by [`heap_access_summary`], so removing a dead
+/// store also requires removing the instructions that compute its operands.
+#[must_use]
+pub fn find_dead_stores(insts: &[RawInst]) -> Vec<usize> {
+    let mut dead = Vec::new();
+    let mut stack = ConstStack::default();
+    // The last store to each constant address in this block, which has not
+    // been read.
+    let mut pending = HashMap::new();
+    for (i, inst) in insts.iter().enumerate() {
+        match (inst, stack.exec(inst)) {
+            (Inst::Store, Some(HeapAccess { addr: Some(addr), .. })) => {
+                if let Some(j) = pending.insert(addr, i) {
+                    dead.push(j);
+                }
+            }
+            (_, Some(HeapAccess { addr: Some(addr), .. })) => {
+                pending.remove(&addr);
+            }
+            (_, Some(HeapAccess { addr: None, store: false })) => pending.clear(),
+            _ => {}
+        }
+        if matches!(
+            inst,
+            Inst::Label(_)
+                | Inst::Call(_)
+                | Inst::Jmp(_)
+                | Inst::Jz(_)
+                | Inst::Jn(_)
+                | Inst::Ret
+                | Inst::End
+                | Inst::Shuffle
+                | Inst::DumpHeap
+                | Inst::Error(_)
+        ) {
+            pending.clear();
+        }
+    }
+    dead.sort_unstable();
+    dead
+}
+
+impl HeapSummary {
+    fn record(&mut self, addr: Option<Integer>, store: bool) {
+        match addr {
+            Some(addr) if store => {
+                self.stored.insert(addr);
+            }
+            Some(addr) => {
+                self.retrieved.insert(addr);
+            }
+            None => self.computed = true,
+        }
+    }
+}
+
+/// A read or write of the heap, at an address that is known, if constant.
+struct HeapAccess {
+    addr: Option<Integer>,
+    store: bool,
+}
+
+/// A stack of values, which are known if they are constant. Values below the
+/// tracked portion are unknown.
+#[derive(Default)]
+struct ConstStack {
+    values: Vec<Option<Integer>>,
+}
+
+impl ConstStack {
+    #[inline]
+    fn push(&mut self, value: Option<Integer>) {
+        self.values.push(value);
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Option<Integer> {
+        self.values.pop().flatten()
+    }
+
+    #[inline]
+    fn copy(&mut self, n: usize) {
+        let len = self.values.len();
+        let value = (n < len)
+            .then(|| self.values[len - n - 1].clone())
+            .flatten();
+        self.values.push(value);
+    }
+
+    #[inline]
+    fn drop(&mut self, n: usize) {
+        self.values.truncate(self.values.len().saturating_sub(n));
+    }
+
+    #[inline]
+    fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Executes an instruction on the tracked values, and returns the heap
+    /// access that it performs, if any.
+    fn exec(&mut self, inst: &RawInst) -> Option<HeapAccess> {
+        match inst {
+            Inst::Push(n) => self.push(Some(convert::integer_from_signed_bits(n))),
+            Inst::Dup => self.copy(0),
+            Inst::Copy(n) => match convert::integer_from_signed_bits(n).to_usize() {
+                Some(n) => self.copy(n),
+                None => self.push(None),
+            },
+            Inst::Swap => {
+                let x = self.pop();
+                let y = self.pop();
+                self.push(x);
+                self.push(y);
+            }
+            Inst::Drop | Inst::Jz(_) | Inst::Jn(_) | Inst::Printc | Inst::Printi => {
+                self.pop();
+            }
+            Inst::Slide(n) => {
+                let top = self.pop();
+                match convert::integer_from_signed_bits(n).to_usize() {
+                    Some(n) => self.drop(n),
+                    None => self.clear(),
+                }
+                self.push(top);
+            }
+            Inst::Add | Inst::Sub | Inst::Mul => {
+                let y = self.pop();
+                let x = self.pop();
+                self.push(match (x, y) {
+                    (Some(x), Some(y)) => Some(match inst {
+                        Inst::Add => x + y,
+                        Inst::Sub => x - y,
+                        _ => x * y,
+                    }),
+                    _ => None,
+                });
+            }
+            Inst::Div | Inst::Mod => {
+                self.pop();
+                self.pop();
+                self.push(None);
+            }
+            Inst::Store => {
+                self.pop();
+                return Some(HeapAccess { addr: self.pop(), store: true });
+            }
+            Inst::Retrieve => {
+                let addr = self.pop();
+                self.push(None);
+                return Some(HeapAccess { addr, store: false });
+            }
+            Inst::Readc | Inst::Readi => return Some(HeapAccess { addr: self.pop(), store: true }),
+            Inst::Label(_)
+            | Inst::Call(_)
+            | Inst::Jmp(_)
+            | Inst::Ret
+            | Inst::End
+            | Inst::Shuffle
+            | Inst::Error(_) => self.clear(),
+            Inst::DumpStack | Inst::DumpHeap | Inst::DumpTrace => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+    use crate::ws::tests::push;
+
+    #[test]
+    fn constant_addresses() {
+        let insts = vec![
+            Inst::Push(bitvec![0, 1, 0]),
+            Inst::Push(bitvec![0, 1, 0, 1]),
+            Inst::Store,
+            Inst::Push(bitvec![0, 1]),
+            Inst::Push(bitvec![0, 1]),
+            Inst::Add,
+            Inst::Retrieve,
+            Inst::Push(bitvec![0, 1, 1]),
+            Inst::Readc,
+            Inst::End,
+        ];
+        let summary = heap_access_summary(&insts);
+        assert_eq!(
+            BTreeSet::from([Integer::from(2), Integer::from(3)]),
+            summary.stored
+        );
+        assert_eq!(BTreeSet::from([Integer::from(2)]), summary.retrieved);
+        assert!(!summary.computed);
+    }
+
+    #[test]
+    fn uses_heap() {
+        let stack_only = [
+            Inst::Push(bitvec![0, 1]),
+            Inst::Dup,
+            Inst::Printi,
+            Inst::End,
+        ];
+        assert!(!super::uses_heap(&stack_only));
+        assert!(!super::uses_heap(&[]));
+        for inst in [Inst::Store, Inst::Retrieve, Inst::Readc, Inst::Readi] {
+            assert!(super::uses_heap(&[Inst::Push(bitvec![0, 1]), inst]));
+        }
+    }
+
+    #[test]
+    fn dead_stores() {
+        let insts = vec![
+            push(1),
+            push(10),
+            Inst::Store, // 2: dead
+            push(2),
+            push(20),
+            Inst::Store, // 5: read before overwritten
+            push(2),
+            Inst::Retrieve,
+            Inst::Drop,
+            push(1),
+            push(11),
+            Inst::Store, // 11: dead
+            push(2),
+            push(21),
+            Inst::Store, // 14: overwritten in the next block
+            push(1),
+            push(12),
+            Inst::Store, // 17: overwritten after a computed read
+            Inst::Dup,
+            Inst::Retrieve,
+            Inst::Drop,
+            push(1),
+            push(13),
+            Inst::Store,
+            Inst::Label(bitvec![1]),
+            push(2),
+            push(22),
+            Inst::Store,
+            Inst::End,
+        ];
+        assert_eq!(vec![2, 11], find_dead_stores(&insts));
+    }
+
+    #[test]
+    fn computed_address() {
+        let insts = vec![
+            Inst::Push(bitvec![0, 1]),
+            Inst::Retrieve,
+            Inst::Retrieve,
+            Inst::Label(bitvec![1]),
+            Inst::Push(bitvec![0, 1]),
+            Inst::Store,
+            Inst::End,
+        ];
+        let summary = heap_access_summary(&insts);
+        assert!(summary.stored.is_empty());
+        assert_eq!(BTreeSet::from([Integer::from(1)]), summary.retrieved);
+        assert!(summary.computed);
+    }
+}
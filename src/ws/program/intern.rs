@@ -0,0 +1,137 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::collections::{hash_map::Entry, HashMap};
+
+use bitvec::prelude::*;
+
+use crate::ws::inst::{Inst, InstArg, InstError, RawInst};
+use crate::ws::syntax::LabelId;
+
+/// An instruction with its label interned as a [`LabelId`] into the labels of
+/// an [`InternedProgram`]. Integer arguments are kept as bits.
+pub type InternedInst = Inst<BitVec, LabelId>;
+
+/// A program with each distinct label stored once in a side table, so that
+/// instructions refer to labels by small integer ids, which are cheap to
+/// compare, and the target of each label is resolved ahead of time.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InternedProgram {
+    insts: Vec<InternedInst>,
+    labels: Vec<BitVec>,
+    targets: Vec<Option<usize>>,
+}
+
+impl InternedProgram {
+    /// The instructions, with interned labels.
+    #[inline]
+    #[must_use]
+    pub fn insts(&self) -> &[InternedInst] {
+        &self.insts
+    }
+
+    /// The distinct labels, indexed by id, in order of first definition or
+    /// use.
+    #[inline]
+    #[must_use]
+    pub fn labels(&self) -> &[BitVec] {
+        &self.labels
+    }
+
+    /// The bits of the label with the id.
+    #[inline]
+    #[must_use]
+    pub fn label(&self, id: LabelId) -> &BitSlice {
+        &self.labels[usize::from(id)]
+    }
+
+    /// The index of the first definition of the label with the id, like the
+    /// interpreter, or `None`, if it is only used.
+    #[inline]
+    #[must_use]
+    pub fn target(&self, id: LabelId) -> Option<usize> {
+        self.targets[usize::from(id)]
+    }
+
+    /// Converts back to instructions with labels as bits.
+    #[must_use]
+    pub fn deintern(&self) -> Vec<RawInst> {
+        (self.insts.iter().cloned())
+            .map(|inst| {
+                inst.map_arg(|_, arg| -> Result<_, InstError> {
+                    Ok(match arg {
+                        InstArg::Int(n) => InstArg::Int(n),
+                        InstArg::Label(id) => InstArg::Label(self.labels[usize::from(id)].clone()),
+                    })
+                })
+            })
+            .collect()
+    }
+}
+
+/// Interns the labels of a program, so that each distinct label is stored
+/// once.
+#[must_use]
+pub fn intern(insts: &[RawInst]) -> InternedProgram {
+    let mut ids = HashMap::new();
+    let mut labels = Vec::new();
+    let mut targets = Vec::new();
+    let insts = (insts.iter().cloned().enumerate())
+        .map(|(pc, inst)| {
+            let is_def = matches!(inst, Inst::Label(_));
+            inst.map_arg(|_, arg| -> Result<_, InstError> {
+                Ok(match arg {
+                    InstArg::Int(n) => InstArg::Int(n),
+                    InstArg::Label(l) => {
+                        let id = match ids.entry(l) {
+                            Entry::Occupied(entry) => *entry.get(),
+                            Entry::Vacant(entry) => {
+                                let id = LabelId::from(labels.len());
+                                labels.push(entry.key().clone());
+                                targets.push(None);
+                                *entry.insert(id)
+                            }
+                        };
+                        let target = &mut targets[usize::from(id)];
+                        if is_def && target.is_none() {
+                            *target = Some(pc);
+                        }
+                        InstArg::Label(id)
+                    }
+                })
+            })
+        })
+        .collect();
+    InternedProgram { insts, labels, targets }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let insts = vec![
+            Inst::Push(bitvec![0, 1]),
+            Inst::Call(bitvec![1]),
+            Inst::End,
+            Inst::Label(bitvec![1]),
+            Inst::Jz(bitvec![0, 0]),
+            Inst::Label(bitvec![1]),
+            Inst::Ret,
+        ];
+        let prog = intern(&insts);
+        assert_eq!(&[bitvec![1], bitvec![0, 0]], prog.labels());
+        assert_eq!(Inst::Call(LabelId(0)), prog.insts()[1]);
+        assert_eq!(Inst::Label(LabelId(0)), prog.insts()[5]);
+        assert_eq!(Some(3), prog.target(LabelId(0)));
+        assert_eq!(None, prog.target(LabelId(1)));
+        assert_eq!(bits![0, 0], prog.label(LabelId(1)));
+        assert_eq!(insts, prog.deintern());
+    }
+}
@@ -0,0 +1,57 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use crate::ws::inst::{Inst, RawInst};
+
+/// The I/O instructions in a program, as counts of their static occurrences.
+///
+/// A count of zero means that the program never performs that kind of I/O,
+/// but a nonzero count does not bound how many times it executes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct IoSignature {
+    /// Occurrences of `readc`.
+    pub readc: usize,
+    /// Occurrences of `readi`.
+    pub readi: usize,
+    /// Occurrences of `printc`.
+    pub printc: usize,
+    /// Occurrences of `printi`.
+    pub printi: usize,
+}
+
+impl IoSignature {
+    /// Whether the program may read input.
+    #[inline]
+    #[must_use]
+    pub const fn reads(&self) -> bool {
+        self.readc != 0 || self.readi != 0
+    }
+
+    /// Whether the program may write output.
+    #[inline]
+    #[must_use]
+    pub const fn writes(&self) -> bool {
+        self.printc != 0 || self.printi != 0
+    }
+}
+
+/// Summarizes the I/O performed by a program.
+#[must_use]
+pub fn io_signature(insts: &[RawInst]) -> IoSignature {
+    let mut sig = IoSignature::default();
+    for inst in insts {
+        match inst {
+            Inst::Readc => sig.readc += 1,
+            Inst::Readi => sig.readi += 1,
+            Inst::Printc => sig.printc += 1,
+            Inst::Printi => sig.printi += 1,
+            _ => {}
+        }
+    }
+    sig
+}
@@ -0,0 +1,107 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::collections::HashSet;
+use std::iter::FusedIterator;
+
+use bitvec::vec::BitVec;
+
+/// A generator of unique labels, for code generation.
+///
+/// Labels are generated in order of length, then lexicographically: the empty
+/// label, `0`, `1`, `00`, `01`, and so on, so each is as short as possible.
+/// Labels that are already used by a program can be avoided.
+#[derive(Clone, Debug, Default)]
+pub struct LabelGen {
+    next: u64,
+    used: HashSet<BitVec>,
+}
+
+impl LabelGen {
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        LabelGen::default()
+    }
+
+    /// Constructs a generator, that never generates any of the given labels.
+    #[must_use]
+    pub fn avoiding<I: IntoIterator<Item = BitVec>>(used: I) -> Self {
+        LabelGen {
+            next: 0,
+            used: used.into_iter().collect(),
+        }
+    }
+
+    /// Prevents a label from being generated.
+    #[inline]
+    pub fn avoid(&mut self, label: BitVec) {
+        self.used.insert(label);
+    }
+
+    /// Generates a fresh label.
+    #[must_use]
+    pub fn next_label(&mut self) -> BitVec {
+        loop {
+            let label = Self::nth_label(self.next);
+            self.next += 1;
+            if !self.used.contains(&label) {
+                return label;
+            }
+        }
+    }
+
+    /// The `n`th label in order of length, then lexicographically.
+    fn nth_label(n: u64) -> BitVec {
+        // Labels of length `len` start at index `2^len - 1`.
+        let n = n + 1;
+        let len = (u64::BITS - 1 - n.leading_zeros()) as usize;
+        let value = n - (1 << len);
+        (0..len).rev().map(|i| value >> i & 1 != 0).collect()
+    }
+}
+
+impl Iterator for LabelGen {
+    type Item = BitVec;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_label())
+    }
+}
+
+impl FusedIterator for LabelGen {}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn minimal_unique() {
+        let labels = LabelGen::new().take(1000).collect::<Vec<_>>();
+        assert_eq!(
+            vec![bitvec![], bitvec![0], bitvec![1], bitvec![0, 0], bitvec![
+                0, 1
+            ]],
+            labels[..5],
+        );
+        assert_eq!(1000, labels.iter().collect::<HashSet<_>>().len());
+        assert!(labels.windows(2).all(|w| w[0].len() <= w[1].len()));
+        for len in 0..9 {
+            let count = labels.iter().filter(|label| label.len() == len).count();
+            assert_eq!(1 << len, count, "labels of length {len}");
+        }
+
+        let mut labels = LabelGen::avoiding([bitvec![], bitvec![1]]);
+        assert_eq!(bitvec![0], labels.next_label());
+        labels.avoid(bitvec![0, 0]);
+        assert_eq!(bitvec![0, 1], labels.next_label());
+    }
+}
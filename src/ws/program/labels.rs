@@ -0,0 +1,70 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::collections::BTreeSet;
+
+use bitvec::vec::BitVec;
+
+use crate::ws::inst::{Inst, RawInst};
+
+/// Lists the label definitions in a program, as the index of each `label`
+/// instruction and its label, in order. Labels can be written for display
+/// with [`label_text`](crate::ws::assembly::label_text).
+///
+/// Labels that are defined, but not in [`referenced_labels`], are unused, and
+/// referenced labels that are not defined are undefined.
+#[must_use]
+pub fn labels(insts: &[RawInst]) -> Vec<(usize, BitVec)> {
+    (insts.iter().enumerate())
+        .filter_map(|(i, inst)| match inst {
+            Inst::Label(l) => Some((i, l.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The labels referenced by `call`, `jmp`, `jz`, and `jn` in a program.
+#[must_use]
+pub fn referenced_labels(insts: &[RawInst]) -> BTreeSet<BitVec> {
+    (insts.iter())
+        .filter_map(|inst| match inst {
+            Inst::Call(l) | Inst::Jmp(l) | Inst::Jz(l) | Inst::Jn(l) => Some(l.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+    use crate::ws::assembly::label_text;
+
+    #[test]
+    fn unused_and_undefined() {
+        let insts = vec![
+            Inst::Label(bitvec![1]),
+            Inst::Call(bitvec![0, 1]),
+            Inst::Jmp(bitvec![1]),
+            Inst::Label(bitvec![1, 0]),
+            Inst::Jz(bitvec![1]),
+        ];
+        let defs = labels(&insts);
+        assert_eq!(vec![(0, bitvec![1]), (3, bitvec![1, 0])], defs);
+        let refs = referenced_labels(&insts);
+        assert_eq!(BTreeSet::from([bitvec![1], bitvec![0, 1]]), refs);
+
+        let unused = defs.iter().filter(|(_, l)| !refs.contains(l));
+        let unused = unused.map(|(_, l)| label_text(l)).collect::<Vec<_>>();
+        assert_eq!(vec!["2"], unused);
+        let undefined = refs.iter().filter(|l| defs.iter().all(|(_, d)| d != *l));
+        let undefined = undefined.map(label_text).collect::<Vec<_>>();
+        assert_eq!(vec![".01"], undefined);
+    }
+}
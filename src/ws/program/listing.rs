@@ -0,0 +1,83 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::fmt::{self, Display, Formatter, Write};
+
+use crate::ws::assembly::label_text;
+use crate::ws::inst::{Inst, InstError, RawInst};
+use crate::ws::syntax::NumberEncoding;
+
+/// Formats instructions for quick reading, such as in test failures, with one
+/// instruction per line after its index. Integers are written in decimal, as
+/// decoded with the standard sign-magnitude encoding, and labels as in
+/// assembly. Unlike [`disassemble`](crate::ws::assembly::disassemble),
+/// the listing is not meant to be assembled.
+#[must_use]
+pub fn format_listing(insts: &[RawInst]) -> String {
+    let width = insts.len().saturating_sub(1).to_string().len();
+    let mut s = String::new();
+    for (i, inst) in insts.iter().enumerate() {
+        writeln!(
+            s,
+            "{i:>width$}  {}",
+            InstText(inst, NumberEncoding::SignMagnitude)
+        )
+        .unwrap();
+    }
+    s
+}
+
+/// Displays an instruction with its argument decoded with the encoding, as in
+/// [`format_listing`].
+pub(crate) struct InstText<'a>(pub &'a RawInst, pub NumberEncoding);
+
+impl Display for InstText<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let inst = self.0;
+        match inst {
+            Inst::Push(n) | Inst::Copy(n) | Inst::Slide(n) => {
+                write!(f, "{} {}", inst.opcode(), self.1.decode(n))
+            }
+            Inst::Label(l) | Inst::Call(l) | Inst::Jmp(l) | Inst::Jz(l) | Inst::Jn(l) => {
+                write!(f, "{} {}", inst.opcode(), label_text(l))
+            }
+            Inst::Error(InstError::ParseError(err)) => write!(f, "error: {err}"),
+            _ => write!(f, "{}", inst.opcode()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+    use crate::ws::inst::Opcode;
+    use crate::ws::parse::ParseError;
+
+    #[test]
+    fn listing() {
+        let mut insts = vec![Inst::Push(bitvec![1, 0, 1]), Inst::Label(bitvec![0, 1])];
+        insts.extend(
+            [Inst::Dup, Inst::Jz(bitvec![1])]
+                .into_iter()
+                .cycle()
+                .take(8),
+        );
+        let err = ParseError::UnterminatedArg(Opcode::Push, bitvec![1]);
+        insts.push(Inst::Error(InstError::ParseError(err)));
+        let listing = format_listing(&insts);
+        let lines = listing.lines().collect::<Vec<_>>();
+        assert_eq!(" 0  push -1", lines[0]);
+        assert_eq!(" 1  label .01", lines[1]);
+        assert_eq!(" 2  dup", lines[2]);
+        assert_eq!(" 9  jz 1", lines[9]);
+        assert!(lines[10].starts_with("10  error: "));
+        assert_eq!("", format_listing(&[]));
+    }
+}
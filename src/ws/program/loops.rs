@@ -0,0 +1,202 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::ops::Range;
+
+use rug::Integer;
+
+use crate::ws::inst::{Inst, RawInst};
+use crate::ws::syntax::convert;
+
+/// A loop that runs its body once for each decrement of a counter on the top
+/// of the stack, until it reaches zero.
+///
+/// The recognized shape is:
+///
+/// ```ws
+/// head:
+///     dup
+///     jz exit
+///     ; body
+///     push step
+///     sub
+///     jmp head
+/// exit:
+/// ```
+///
+/// where the body is straight-line code, which leaves the stack as it found
+/// it and does not access the counter, so each iteration has the same effect
+/// for the same heap.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LoopInfo {
+    /// The index of the head label.
+    pub head: usize,
+    /// The index of the exit label.
+    pub exit: usize,
+    /// The range of the instructions in the body.
+    pub body: Range<usize>,
+    /// The positive amount subtracted from the counter in each iteration.
+    pub step: Integer,
+    /// Whether the body reads input or writes output, in which case it cannot
+    /// be skipped.
+    pub io: bool,
+}
+
+impl LoopInfo {
+    /// The number of times that the body executes, when the loop is entered
+    /// with `counter`, or `None`, if the loop does not terminate.
+    #[must_use]
+    pub fn trip_count(&self, counter: &Integer) -> Option<Integer> {
+        if *counter < 0 || !counter.is_divisible(&self.step) {
+            return None;
+        }
+        Some(Integer::from(counter / &self.step))
+    }
+}
+
+/// Finds counted loops in a program, with the shape described by
+/// [`LoopInfo`].
+#[must_use]
+pub fn find_counted_loops(insts: &[RawInst]) -> Vec<LoopInfo> {
+    let mut loops = Vec::new();
+    for head in 0..insts.len() {
+        if let Some(info) = match_loop(insts, head) {
+            loops.push(info);
+        }
+    }
+    loops
+}
+
+fn match_loop(insts: &[RawInst], head: usize) -> Option<LoopInfo> {
+    let [Inst::Label(head_label), Inst::Dup, Inst::Jz(exit_label), ..] = &insts[head..] else {
+        return None;
+    };
+    let body_start = head + 3;
+    let jmp = body_start
+        + insts[body_start..]
+            .iter()
+            .position(|inst| body_effect(inst, usize::MAX).is_none())?;
+    if jmp < body_start + 2 {
+        return None;
+    }
+    let [Inst::Push(step), Inst::Sub, Inst::Jmp(back), Inst::Label(exit), ..] = &insts[jmp - 2..]
+    else {
+        return None;
+    };
+    let step = convert::integer_from_signed_bits(step);
+    let body = body_start..jmp - 2;
+    if back != head_label || exit != exit_label || step <= 0 || !balanced(&insts[body.clone()]) {
+        return None;
+    }
+    let io = insts[body.clone()]
+        .iter()
+        .any(|inst| matches!(body_effect(inst, usize::MAX), Some((_, _, true))));
+    Some(LoopInfo {
+        head,
+        exit: jmp + 1,
+        body,
+        step,
+        io,
+    })
+}
+
+/// Checks that a straight-line body leaves the stack as it found it and does
+/// not access values below its starting depth.
+fn balanced(body: &[RawInst]) -> bool {
+    let mut depth = 0;
+    for inst in body {
+        match body_effect(inst, depth) {
+            Some((pops, pushes, _)) => depth = depth - pops + pushes,
+            None => return false,
+        }
+    }
+    depth == 0
+}
+
+/// The stack effect of a straight-line instruction, as the number of values
+/// popped and pushed and whether it performs I/O, or `None`, if it transfers
+/// control or accesses values below `depth`.
+fn body_effect(inst: &RawInst, depth: usize) -> Option<(usize, usize, bool)> {
+    let io = match inst {
+        Inst::Push(_)
+        | Inst::Dup
+        | Inst::Copy(_)
+        | Inst::Swap
+        | Inst::Drop
+        | Inst::Slide(_)
+        | Inst::Add
+        | Inst::Sub
+        | Inst::Mul
+        | Inst::Div
+        | Inst::Mod
+        | Inst::Store
+        | Inst::Retrieve => false,
+        Inst::Printc | Inst::Printi | Inst::Readc | Inst::Readi => true,
+        _ => return None,
+    };
+    let (pops, pushes) = inst.stack_effect();
+    (pops <= depth).then_some((pops, pushes, io))
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn multiply() {
+        // heap[0] += 3 * counter
+        let insts = vec![
+            Inst::Push(bitvec![0, 1, 0, 1]),
+            Inst::Label(bitvec![0]),
+            Inst::Dup,
+            Inst::Jz(bitvec![1]),
+            Inst::Push(bitvec![0]),
+            Inst::Push(bitvec![0]),
+            Inst::Retrieve,
+            Inst::Push(bitvec![0, 1, 1]),
+            Inst::Add,
+            Inst::Store,
+            Inst::Push(bitvec![0, 1]),
+            Inst::Sub,
+            Inst::Jmp(bitvec![0]),
+            Inst::Label(bitvec![1]),
+            Inst::End,
+        ];
+        let expected = LoopInfo {
+            head: 1,
+            exit: 13,
+            body: 4..10,
+            step: Integer::from(1),
+            io: false,
+        };
+        assert_eq!(vec![expected.clone()], find_counted_loops(&insts));
+        assert_eq!(
+            Some(Integer::from(5)),
+            expected.trip_count(&Integer::from(5))
+        );
+        assert_eq!(None, expected.trip_count(&Integer::from(-1)));
+    }
+
+    #[test]
+    fn not_counted() {
+        // The body consumes the counter.
+        let insts = vec![
+            Inst::Label(bitvec![0]),
+            Inst::Dup,
+            Inst::Jz(bitvec![1]),
+            Inst::Printi,
+            Inst::Push(bitvec![0, 1]),
+            Inst::Sub,
+            Inst::Jmp(bitvec![0]),
+            Inst::Label(bitvec![1]),
+        ];
+        assert!(find_counted_loops(&insts).is_empty());
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+//! Analyses and transformations on sequences of Whitespace instructions.
+
+pub use builder::*;
+pub use cfg::*;
+pub use equiv::*;
+pub use explain::*;
+pub use fingerprint::*;
+pub use halt::*;
+pub use heap::*;
+pub use intern::*;
+pub use io::*;
+pub use label_gen::*;
+pub use labels::*;
+pub use listing::*;
+pub use loops::*;
+pub use offsets::*;
+pub use optimize::*;
+pub use report::*;
+pub use sections::*;
+pub use validate::*;
+
+mod builder;
+mod cfg;
+mod equiv;
+mod explain;
+mod fingerprint;
+mod halt;
+mod heap;
+mod intern;
+mod io;
+mod label_gen;
+mod labels;
+mod listing;
+mod loops;
+mod offsets;
+mod optimize;
+mod report;
+mod sections;
+mod validate;
@@ -0,0 +1,91 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::iter::{Enumerate, FusedIterator};
+use std::slice;
+
+use crate::ws::inst::RawInst;
+
+/// An iterator over instructions with their indices and the offsets of their
+/// first tokens in the encoded program, created by [`with_offsets`].
+#[derive(Clone, Debug)]
+pub struct WithOffsets<'a> {
+    iter: Enumerate<slice::Iter<'a, RawInst>>,
+    offset: usize,
+}
+
+/// Iterates instructions as `(index, inst, token_offset)`, for locating
+/// instructions in the encoded program.
+///
+/// # Panics
+///
+/// The iterator panics if an instruction is an `Error`.
+#[inline]
+#[must_use]
+pub fn with_offsets(insts: &[RawInst]) -> WithOffsets<'_> {
+    WithOffsets {
+        iter: insts.iter().enumerate(),
+        offset: 0,
+    }
+}
+
+impl WithOffsets<'_> {
+    /// The token offset of the next instruction, which, once the iterator is
+    /// exhausted, is the token length of the program.
+    #[inline]
+    #[must_use]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for WithOffsets<'a> {
+    type Item = (usize, &'a RawInst, usize);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, inst) = self.iter.next()?;
+        let offset = self.offset;
+        self.offset += inst.token_len();
+        Some((i, inst, offset))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl ExactSizeIterator for WithOffsets<'_> {}
+
+impl FusedIterator for WithOffsets<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+    use crate::ws::inst::Inst;
+
+    #[test]
+    fn offsets() {
+        let insts = vec![
+            Inst::Push(bitvec![0, 1]),
+            Inst::Dup,
+            Inst::Jmp(bitvec![]),
+            Inst::End,
+        ];
+        let mut iter = with_offsets(&insts);
+        let offsets = iter
+            .by_ref()
+            .map(|(i, _, offset)| (i, offset))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(0, 0), (1, 5), (2, 8), (3, 12)], offsets);
+        assert_eq!(15, iter.offset());
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use crate::ws::inst::{Inst, RawInst};
+
+/// A peephole optimization, that rewrites a short window of instructions.
+///
+/// Rules assume that the program does not underflow the stack, so, for
+/// example, `dup drop` may be removed, even though `dup` would trap on an
+/// empty stack.
+pub trait PeepholeRule {
+    /// Inspects the instructions at the start of `window` and, if the rule
+    /// matches, returns the number of instructions matched and their
+    /// replacement.
+    ///
+    /// To ensure that [`Optimizer::run`] reaches a fixpoint, a replacement
+    /// must be smaller than the instructions it matched. A rewrite that does
+    /// not shrink, or that matches more instructions than are in `window`, is
+    /// ignored, as if the rule had not matched.
+    fn apply(&self, window: &[RawInst]) -> Option<(usize, Vec<RawInst>)>;
+}
+
+/// A peephole optimizer with an extensible set of rules.
+#[derive(Default)]
+pub struct Optimizer {
+    rules: Vec<Box<dyn PeepholeRule>>,
+}
+
+impl Optimizer {
+    /// Constructs an optimizer with no rules.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Optimizer::default()
+    }
+
+    /// Constructs an optimizer with the built-in rules.
+    #[must_use]
+    pub fn with_builtin_rules() -> Self {
+        let mut opt = Optimizer::new();
+        opt.register(PushDrop);
+        opt.register(DupDrop);
+        opt.register(SwapSwap);
+        opt.register(JmpNext);
+        opt
+    }
+
+    /// Adds a rule, which is tried after all previously-registered rules.
+    #[inline]
+    pub fn register<R: PeepholeRule + 'static>(&mut self, rule: R) {
+        self.rules.push(Box::new(rule));
+    }
+
+    /// Applies the rules until no more match and returns the number of
+    /// rewrites performed.
+    pub fn run(&self, insts: &mut Vec<RawInst>) -> usize {
+        let mut rewrites = 0;
+        loop {
+            let mut changed = false;
+            let mut i = 0;
+            while i < insts.len() {
+                let window = &insts[i..];
+                let rewrite = self.rules.iter().find_map(|rule| {
+                    rule.apply(window).filter(|(len, replacement)| {
+                        *len <= window.len() && replacement.len() < *len
+                    })
+                });
+                match rewrite {
+                    Some((len, replacement)) => {
+                        insts.splice(i..i + len, replacement);
+                        rewrites += 1;
+                        changed = true;
+                    }
+                    None => i += 1,
+                }
+            }
+            if !changed {
+                return rewrites;
+            }
+        }
+    }
+}
+
+/// Removes a value that is pushed, then immediately dropped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PushDrop;
+
+/// Removes a value that is duplicated, then immediately dropped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DupDrop;
+
+/// Removes two consecutive swaps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SwapSwap;
+
+/// Removes a jump to the label that immediately follows it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct JmpNext;
+
+impl PeepholeRule for PushDrop {
+    fn apply(&self, window: &[RawInst]) -> Option<(usize, Vec<RawInst>)> {
+        matches!(window, [Inst::Push(_), Inst::Drop, ..]).then(|| (2, Vec::new()))
+    }
+}
+
+impl PeepholeRule for DupDrop {
+    fn apply(&self, window: &[RawInst]) -> Option<(usize, Vec<RawInst>)> {
+        matches!(window, [Inst::Dup, Inst::Drop, ..]).then(|| (2, Vec::new()))
+    }
+}
+
+impl PeepholeRule for SwapSwap {
+    fn apply(&self, window: &[RawInst]) -> Option<(usize, Vec<RawInst>)> {
+        matches!(window, [Inst::Swap, Inst::Swap, ..]).then(|| (2, Vec::new()))
+    }
+}
+
+impl PeepholeRule for JmpNext {
+    fn apply(&self, window: &[RawInst]) -> Option<(usize, Vec<RawInst>)> {
+        match window {
+            [Inst::Jmp(l1), label @ Inst::Label(l2), ..] if l1 == l2 => {
+                Some((2, vec![label.clone()]))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn builtin_rules() {
+        let mut insts = vec![
+            Inst::Push(bitvec![0, 1]),
+            Inst::Dup,
+            Inst::Swap,
+            Inst::Push(bitvec![0, 1, 0]),
+            Inst::Drop,
+            Inst::Swap,
+            Inst::Drop,
+            Inst::Jmp(bitvec![1]),
+            Inst::Label(bitvec![1]),
+            Inst::End,
+        ];
+        let rewrites = Optimizer::with_builtin_rules().run(&mut insts);
+        assert_eq!(
+            vec![
+                Inst::Push(bitvec![0, 1]),
+                Inst::Label(bitvec![1]),
+                Inst::End
+            ],
+            insts
+        );
+        assert_eq!(4, rewrites);
+    }
+
+    #[test]
+    fn custom_rule() {
+        /// Folds `push 0 add` to nothing.
+        struct AddZero;
+        impl PeepholeRule for AddZero {
+            fn apply(&self, window: &[RawInst]) -> Option<(usize, Vec<RawInst>)> {
+                match window {
+                    [Inst::Push(n), Inst::Add, ..] if n.not_any() => Some((2, Vec::new())),
+                    _ => None,
+                }
+            }
+        }
+
+        let mut insts = vec![Inst::Push(bitvec![0, 0]), Inst::Add, Inst::Printi];
+        let mut opt = Optimizer::new();
+        assert_eq!(0, opt.run(&mut insts));
+        opt.register(AddZero);
+        assert_eq!(1, opt.run(&mut insts));
+        assert_eq!(vec![Inst::Printi], insts);
+    }
+
+    #[test]
+    fn invalid_rewrites() {
+        /// Matches past the end of the window.
+        struct Overrun;
+        impl PeepholeRule for Overrun {
+            fn apply(&self, window: &[RawInst]) -> Option<(usize, Vec<RawInst>)> {
+                Some((window.len() + 1, Vec::new()))
+            }
+        }
+        /// Replaces an instruction with itself, which never shrinks.
+        struct Identity;
+        impl PeepholeRule for Identity {
+            fn apply(&self, window: &[RawInst]) -> Option<(usize, Vec<RawInst>)> {
+                Some((1, window[..1].to_vec()))
+            }
+        }
+        /// Matches no instructions.
+        struct Empty;
+        impl PeepholeRule for Empty {
+            fn apply(&self, _window: &[RawInst]) -> Option<(usize, Vec<RawInst>)> {
+                Some((0, Vec::new()))
+            }
+        }
+
+        let mut opt = Optimizer::new();
+        opt.register(Overrun);
+        opt.register(Identity);
+        opt.register(Empty);
+        opt.register(DupDrop);
+        let mut insts = vec![Inst::Dup, Inst::Drop, Inst::End];
+        assert_eq!(1, opt.run(&mut insts));
+        assert_eq!(vec![Inst::End], insts);
+    }
+}
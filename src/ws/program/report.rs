@@ -0,0 +1,149 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+use crate::ws::inst::{Inst, Opcode, RawInst};
+
+/// A summary of the effect of optimizing a program, as computed by
+/// [`optimization_report`].
+///
+/// It displays as the tokens saved, e.g., `saved 142 tokens (18%)`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct OptReport {
+    /// The number of tokens in the original program.
+    pub original_tokens: usize,
+    /// The number of tokens in the optimized program.
+    pub optimized_tokens: usize,
+    /// The number of instructions in the original program.
+    pub original_insts: usize,
+    /// The number of instructions in the optimized program.
+    pub optimized_insts: usize,
+    /// The occurrences of each opcode in the original and optimized programs,
+    /// respectively, for the opcodes whose occurrences changed.
+    pub opcode_deltas: BTreeMap<Opcode, (usize, usize)>,
+}
+
+impl OptReport {
+    /// The number of tokens removed by optimizing, or `0`, if the program
+    /// grew.
+    #[inline]
+    #[must_use]
+    pub const fn tokens_saved(&self) -> usize {
+        self.original_tokens.saturating_sub(self.optimized_tokens)
+    }
+}
+
+impl Display for OptReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.optimized_tokens > self.original_tokens {
+            let added = self.optimized_tokens - self.original_tokens;
+            return write!(f, "added {added} tokens");
+        }
+        let saved = self.tokens_saved();
+        let percent = (saved * 100).checked_div(self.original_tokens).unwrap_or(0);
+        write!(f, "saved {saved} tokens ({percent}%)")
+    }
+}
+
+/// Counts the occurrences of each opcode in a program, skipping `Error`
+/// instructions. The map is ordered by opcode, so printing it is reproducible
+/// and it can be snapshotted.
+#[must_use]
+pub fn opcode_histogram(insts: &[RawInst]) -> BTreeMap<Opcode, usize> {
+    let mut counts = BTreeMap::new();
+    for inst in insts {
+        if !matches!(inst, Inst::Error(_)) {
+            *counts.entry(inst.opcode()).or_default() += 1;
+        }
+    }
+    counts
+}
+
+/// Compares a program before and after optimizing.
+///
+/// # Panics
+///
+/// Panics if either program contains an `Error` instruction.
+#[must_use]
+pub fn optimization_report(original: &[RawInst], optimized: &[RawInst]) -> OptReport {
+    let mut counts = BTreeMap::<Opcode, (usize, usize)>::new();
+    for inst in original {
+        counts.entry(inst.opcode()).or_default().0 += 1;
+    }
+    for inst in optimized {
+        counts.entry(inst.opcode()).or_default().1 += 1;
+    }
+    counts.retain(|_, (before, after)| before != after);
+    OptReport {
+        original_tokens: original.iter().map(RawInst::token_len).sum(),
+        optimized_tokens: optimized.iter().map(RawInst::token_len).sum(),
+        original_insts: original.len(),
+        optimized_insts: optimized.len(),
+        opcode_deltas: counts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+    use crate::ws::parse::ParseError;
+    use crate::ws::program::Optimizer;
+
+    #[test]
+    fn report() {
+        let original = vec![Inst::Push(bitvec![0, 1]), Inst::Dup, Inst::Drop, Inst::End];
+        let mut optimized = original.clone();
+        Optimizer::with_builtin_rules().run(&mut optimized);
+        let report = optimization_report(&original, &optimized);
+        let expected = OptReport {
+            original_tokens: 14,
+            optimized_tokens: 8,
+            original_insts: 4,
+            optimized_insts: 2,
+            opcode_deltas: BTreeMap::from([(Opcode::Dup, (1, 0)), (Opcode::Drop, (1, 0))]),
+        };
+        assert_eq!(expected, report);
+        assert_eq!("saved 6 tokens (42%)", report.to_string());
+        assert_eq!(
+            "added 6 tokens",
+            optimization_report(&optimized, &original).to_string()
+        );
+    }
+
+    #[test]
+    fn histogram_order() {
+        let err = ParseError::UnterminatedArg(Opcode::Push, bitvec![]);
+        let mut insts = vec![
+            Inst::End,
+            Inst::Printi,
+            Inst::Push(bitvec![0, 1]),
+            Inst::Error(err.into()),
+            Inst::Dup,
+            Inst::Push(bitvec![0]),
+            Inst::Add,
+        ];
+        let a = opcode_histogram(&insts).into_iter().collect::<Vec<_>>();
+        let b = opcode_histogram(&insts).into_iter().collect::<Vec<_>>();
+        assert_eq!(a, b);
+        let expected = vec![
+            (Opcode::Push, 2),
+            (Opcode::Dup, 1),
+            (Opcode::Add, 1),
+            (Opcode::End, 1),
+            (Opcode::Printi, 1),
+        ];
+        assert_eq!(expected, a);
+        insts.reverse();
+        let reversed = opcode_histogram(&insts).into_iter().collect::<Vec<_>>();
+        assert_eq!(a, reversed);
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use bitvec::vec::BitVec;
+
+use crate::ws::assembly::label_text;
+use crate::ws::inst::{Inst, RawInst};
+
+/// A region of a program, from a label to the next label, as split by
+/// [`sections`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Section<'a> {
+    /// The label that starts the section, or `None` for the entry section,
+    /// before the first label.
+    pub label: Option<&'a BitVec>,
+    /// The instructions in the section, starting with its `label`.
+    pub insts: &'a [RawInst],
+}
+
+impl Section<'_> {
+    /// The name of the section for display, which is its label, written as in
+    /// the assembly format, or `entry`.
+    #[must_use]
+    pub fn name(&self) -> String {
+        match self.label {
+            Some(l) => label_text(l),
+            None => "entry".to_owned(),
+        }
+    }
+}
+
+/// Splits a program at each label, for an outline of its structure. Code
+/// before the first label forms an unlabeled entry section, which is omitted,
+/// if empty.
+#[must_use]
+pub fn sections(insts: &[RawInst]) -> Vec<Section<'_>> {
+    let mut sections = Vec::new();
+    let mut start = 0;
+    let mut label = None;
+    for (i, inst) in insts.iter().enumerate() {
+        if let Inst::Label(l) = inst {
+            if i != 0 {
+                sections.push(Section { label, insts: &insts[start..i] });
+            }
+            start = i;
+            label = Some(l);
+        }
+    }
+    if start != insts.len() {
+        sections.push(Section { label, insts: &insts[start..] });
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn split() {
+        let insts = vec![
+            Inst::Call(bitvec![1]),
+            Inst::End,
+            Inst::Label(bitvec![1]),
+            Inst::Label(bitvec![0, 1]),
+            Inst::Ret,
+        ];
+        let (l1, l01) = (bitvec![1], bitvec![0, 1]);
+        let expected = vec![
+            Section { label: None, insts: &insts[0..2] },
+            Section {
+                label: Some(&l1),
+                insts: &insts[2..3],
+            },
+            Section {
+                label: Some(&l01),
+                insts: &insts[3..],
+            },
+        ];
+        let sections = sections(&insts);
+        assert_eq!(expected, sections);
+        let names = sections.iter().map(Section::name).collect::<Vec<_>>();
+        assert_eq!(["entry", "1", ".01"], &names[..]);
+        assert_eq!(1, super::sections(&insts[2..3]).len());
+        assert!(super::sections(&[]).is_empty());
+    }
+}
@@ -0,0 +1,405 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use arrayvec::ArrayVec;
+
+use crate::ws::inst::{Inst, RawInst};
+use crate::ws::program::labels;
+use crate::ws::syntax::convert;
+
+/// A problem in a program, that is found without running it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ValidationError {
+    /// The instruction references a label that is not defined.
+    UndefinedLabel { index: usize },
+    /// The instruction is reached on some path with fewer values on the stack
+    /// than it consumes.
+    StackUnderflow { index: usize },
+    /// No `end` is reachable from the entry and execution cannot otherwise
+    /// stop, so the program never halts. This is a warning, since some
+    /// programs intentionally loop forever.
+    NoReachableHalt,
+    /// The label definition has different bits than the earlier definition
+    /// at `first`, but the same numeric value, so they collide under
+    /// [`LabelSemantics::NumericValue`].
+    LabelAliasing { index: usize, first: usize },
+    /// The operand of `push` is longer than
+    /// [`ValidateOptions::max_push_bits`], which often indicates a mistake.
+    /// This is a warning.
+    LargePushOperand { index: usize, bit_len: usize },
+}
+
+/// Options for [`validate_with`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ValidateOptions {
+    /// How labels are identified, when checking for aliasing.
+    pub label_semantics: LabelSemantics,
+    /// The longest `push` operand, in bits including the sign, that is not
+    /// warned about. It defaults to 256 and can be raised for programs with
+    /// intentionally large constants.
+    pub max_push_bits: usize,
+}
+
+impl Default for ValidateOptions {
+    #[inline]
+    fn default() -> Self {
+        ValidateOptions {
+            label_semantics: LabelSemantics::default(),
+            max_push_bits: 256,
+        }
+    }
+}
+
+/// How an implementation identifies labels, for [`ValidateOptions`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LabelSemantics {
+    /// Labels are distinct when their bits differ, like this interpreter and
+    /// wspace.
+    #[default]
+    ExactBits,
+    /// Labels are read as unsigned integers, so labels that differ only in
+    /// leading zeros are the same.
+    NumericValue,
+}
+
+impl ValidationError {
+    /// The index of the instruction with the problem, if it is specific to
+    /// one.
+    #[inline]
+    #[must_use]
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            ValidationError::UndefinedLabel { index }
+            | ValidationError::StackUnderflow { index }
+            | ValidationError::LabelAliasing { index, .. }
+            | ValidationError::LargePushOperand { index, .. } => Some(*index),
+            ValidationError::NoReachableHalt => None,
+        }
+    }
+
+    /// Whether the problem does not necessarily make the program incorrect.
+    #[inline]
+    #[must_use]
+    pub fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            ValidationError::NoReachableHalt | ValidationError::LargePushOperand { .. }
+        )
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::UndefinedLabel { index } => {
+                write!(f, "undefined label at instruction {index}")
+            }
+            ValidationError::StackUnderflow { index } => {
+                write!(f, "stack underflow at instruction {index}")
+            }
+            ValidationError::NoReachableHalt => write!(f, "no reachable end"),
+            ValidationError::LabelAliasing { index, first } => write!(
+                f,
+                "label at instruction {index} has the same value as the label at instruction {first}",
+            ),
+            ValidationError::LargePushOperand { index, bit_len } => {
+                write!(f, "push of a {bit_len}-bit operand at instruction {index}")
+            }
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Finds `push` operands that are longer than the maximum and, under numeric
+/// semantics, label definitions with the same numeric value as an earlier
+/// definition, but different bits.
+fn check_operands(insts: &[RawInst], options: ValidateOptions, errors: &mut Vec<ValidationError>) {
+    for (i, inst) in insts.iter().enumerate() {
+        if let Inst::Push(n) = inst {
+            if n.len() > options.max_push_bits {
+                errors.push(ValidationError::LargePushOperand { index: i, bit_len: n.len() });
+            }
+        }
+    }
+    if options.label_semantics == LabelSemantics::ExactBits {
+        return;
+    }
+    let mut values = HashMap::new();
+    for (i, l) in labels(insts) {
+        let &mut (first, ref bits) = values
+            .entry(convert::integer_from_unsigned_bits(&l))
+            .or_insert((i, l.clone()));
+        if *bits != l {
+            errors.push(ValidationError::LabelAliasing { index: i, first });
+        }
+    }
+}
+
+/// The minimum stack height on entry to an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Height {
+    Unreached,
+    Known(usize),
+    Unknown,
+}
+
+/// Checks a program for undefined labels, for instructions that underflow
+/// the stack, for whether it can halt, and for `push` operands longer than
+/// the default [`ValidateOptions::max_push_bits`]. The errors are ordered by
+/// instruction index, followed by those for the whole program.
+///
+/// The stack height is tracked from the entry as a lower bound, taking the
+/// minimum where paths merge. The height after a `call` is unknown, since it
+/// depends on the subroutine, and heights that are lowered by a loop more
+/// than once become unknown, so the analysis terminates. Instructions with an
+/// unknown height are not checked.
+///
+/// A `call` is assumed to return, so the program can halt when an `end` is
+/// reachable from the entry through calls and jumps, when execution can
+/// continue past the last instruction, or when it reaches an instruction that
+/// always traps.
+#[inline]
+#[must_use]
+pub fn validate(insts: &[RawInst]) -> Vec<ValidationError> {
+    validate_with(insts, ValidateOptions::default())
+}
+
+/// Checks a program like [`validate`], with the given options. Under
+/// [`LabelSemantics::NumericValue`], it also checks for label definitions that
+/// alias an earlier definition by numeric value, which would run differently
+/// on an implementation that treats labels numerically.
+#[must_use]
+pub fn validate_with(insts: &[RawInst], options: ValidateOptions) -> Vec<ValidationError> {
+    let mut labels = HashMap::new();
+    for (i, inst) in insts.iter().enumerate() {
+        if let Inst::Label(l) = inst {
+            labels.entry(l).or_insert(i);
+        }
+    }
+
+    let mut errors = Vec::new();
+    check_operands(insts, options, &mut errors);
+    for (i, inst) in insts.iter().enumerate() {
+        if let Inst::Call(l) | Inst::Jmp(l) | Inst::Jz(l) | Inst::Jn(l) = inst {
+            if !labels.contains_key(l) {
+                errors.push(ValidationError::UndefinedLabel { index: i });
+            }
+        }
+    }
+
+    let mut heights = vec![Height::Unreached; insts.len()];
+    let mut lowered = vec![false; insts.len()];
+    let mut work = Vec::new();
+    let mut falls_off = insts.is_empty();
+    if !insts.is_empty() {
+        heights[0] = Height::Known(0);
+        work.push(0);
+    }
+    while let Some(i) = work.pop() {
+        let inst = &insts[i];
+        if let Inst::Error(_) = inst {
+            continue;
+        }
+        let out = match heights[i] {
+            Height::Known(h) => {
+                let (pops, pushes) = inst.stack_effect();
+                if pops > h {
+                    Height::Unknown
+                } else {
+                    Height::Known(h - pops + pushes)
+                }
+            }
+            height => height,
+        };
+        let mut succs = ArrayVec::<(usize, Height), 2>::new();
+        match inst {
+            Inst::Jmp(l) => succs.extend(labels.get(l).map(|&j| (j, out))),
+            Inst::Jz(l) | Inst::Jn(l) => {
+                succs.push((i + 1, out));
+                succs.extend(labels.get(l).map(|&j| (j, out)));
+            }
+            Inst::Call(l) => {
+                succs.push((i + 1, Height::Unknown));
+                succs.extend(labels.get(l).map(|&j| (j, out)));
+            }
+            Inst::Ret | Inst::End => {}
+            _ => succs.push((i + 1, out)),
+        }
+        for (j, height) in succs {
+            if j >= insts.len() {
+                falls_off = true;
+                continue;
+            }
+            let old = heights[j];
+            let merged = match (old, height) {
+                (Height::Unreached, h) | (Height::Known(_), h @ Height::Unknown) => h,
+                (Height::Known(a), Height::Known(b)) if b < a => {
+                    if lowered[j] {
+                        Height::Unknown
+                    } else {
+                        lowered[j] = true;
+                        Height::Known(b)
+                    }
+                }
+                (old, _) => old,
+            };
+            if merged != old {
+                heights[j] = merged;
+                work.push(j);
+            }
+        }
+    }
+
+    for (i, (inst, &height)) in insts.iter().zip(&heights).enumerate() {
+        match (inst, height) {
+            (Inst::Error(_), _) => {}
+            (_, Height::Known(h)) if inst.stack_effect().0 > h => {
+                errors.push(ValidationError::StackUnderflow { index: i });
+            }
+            _ => {}
+        }
+    }
+    let halts = insts.iter().zip(&heights).any(|(inst, &height)| {
+        height != Height::Unreached
+            && match inst {
+                Inst::End | Inst::Error(_) => true,
+                Inst::Call(l) | Inst::Jmp(l) | Inst::Jz(l) | Inst::Jn(l) => !labels.contains_key(l),
+                _ => false,
+            }
+    });
+    if !halts && !falls_off {
+        errors.push(ValidationError::NoReachableHalt);
+    }
+    errors.sort_by_key(|err| err.index().unwrap_or(usize::MAX));
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use bitvec::prelude::*;
+
+    use super::*;
+    use crate::ws::program::ProgramBuilder;
+
+    #[test]
+    fn underflow() {
+        let mut b = ProgramBuilder::new();
+        b.push(1).add().end();
+        assert_eq!(
+            vec![ValidationError::StackUnderflow { index: 1 }],
+            validate(b.insts())
+        );
+
+        // Only one path to the label leaves a value on the stack.
+        let mut b = ProgramBuilder::new();
+        b.push(0)
+            .jz(bits![0])
+            .push(1)
+            .label(bits![0])
+            .drop()
+            .jmp(bits![1])
+            .end();
+        assert_eq!(
+            vec![
+                ValidationError::StackUnderflow { index: 4 },
+                ValidationError::UndefinedLabel { index: 5 },
+            ],
+            validate(b.insts())
+        );
+    }
+
+    #[test]
+    fn unknown_heights() {
+        // The effect of the subroutine is not tracked.
+        let mut b = ProgramBuilder::new();
+        b.call(bits![0]).drop().end().label(bits![0]).push(1).ret();
+        assert!(validate(b.insts()).is_empty());
+
+        // A loop that lowers the stack by one each iteration.
+        let mut b = ProgramBuilder::new();
+        b.push(1)
+            .push(0)
+            .push(1)
+            .label(bits![0])
+            .jz(bits![1])
+            .jmp(bits![0])
+            .label(bits![1])
+            .end();
+        assert!(validate(b.insts()).is_empty());
+    }
+
+    #[test]
+    fn no_reachable_halt() {
+        let mut b = ProgramBuilder::new();
+        b.label(bits![0]).push(1).printi().jmp(bits![0]).end();
+        assert_eq!(vec![ValidationError::NoReachableHalt], validate(b.insts()));
+        assert!(validate(b.insts())[0].is_warning());
+
+        // The end is reachable after the call returns.
+        let mut b = ProgramBuilder::new();
+        b.call(bits![0]).end().label(bits![0]).ret();
+        assert!(validate(b.insts()).is_empty());
+
+        // Execution can continue past the last instruction.
+        let mut b = ProgramBuilder::new();
+        b.push(1).printi();
+        assert!(validate(b.insts()).is_empty());
+        assert!(validate(&[]).is_empty());
+    }
+
+    #[test]
+    fn label_aliasing() {
+        let mut b = ProgramBuilder::new();
+        b.label(bits![1])
+            .label(bits![0, 1])
+            .label(bits![1])
+            .label(bits![0, 0, 1])
+            .label(bits![1, 0])
+            .end();
+        assert!(validate(b.insts()).is_empty());
+        let options = ValidateOptions {
+            label_semantics: LabelSemantics::NumericValue,
+            ..ValidateOptions::default()
+        };
+        let errors = validate_with(b.insts(), options);
+        assert_eq!(
+            vec![
+                ValidationError::LabelAliasing { index: 1, first: 0 },
+                ValidationError::LabelAliasing { index: 3, first: 0 },
+            ],
+            errors
+        );
+        assert!(!errors[0].is_warning());
+    }
+
+    #[test]
+    fn large_push_operand() {
+        let long = BitVec::repeat(true, 257);
+        let insts = [
+            Inst::Push(BitVec::repeat(true, 256)),
+            Inst::Push(long),
+            Inst::End,
+        ];
+        let errors = validate(&insts);
+        assert_eq!(
+            vec![ValidationError::LargePushOperand { index: 1, bit_len: 257 }],
+            errors
+        );
+        assert!(errors[0].is_warning());
+        let options = ValidateOptions {
+            max_push_bits: 257,
+            ..ValidateOptions::default()
+        };
+        assert!(validate_with(&insts, options).is_empty());
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use crate::text::EncodingError;
+use crate::ws::inst::Inst;
+use crate::ws::parse::Parser;
+use crate::ws::token::{bit_unpack_container, LexerExt, Mapping, MappingLexer};
+use crate::ws::vm::Vm;
+use crate::ws::WsError;
+
+/// Reads, parses, and runs a program from a file, for tools that just run a
+/// file.
+///
+/// A file that starts with [`CONTAINER_MAGIC`](crate::ws::token::CONTAINER_MAGIC)
+/// is unpacked as a bit-packed container. Anything else is lexed as source with
+/// the mapping.
+pub fn run_file<P, R, W>(
+    path: P,
+    map: Mapping<u8>,
+    input: &mut R,
+    output: &mut W,
+) -> Result<(), WsError>
+where
+    P: AsRef<Path>,
+    R: BufRead,
+    W: Write,
+{
+    let src = fs::read(path)?;
+    let toks = match bit_unpack_container(&src) {
+        Some(toks) => toks?,
+        None => MappingLexer::new_bytes(&src, map).collect_tokens()?,
+    };
+    let mut insts = Vec::new();
+    for inst in Parser::new(toks.into_iter().map(Ok::<_, EncodingError>)) {
+        if let Inst::Error(err) = inst {
+            return Err(err.into());
+        }
+        insts.push(inst);
+    }
+    Vm::new(insts).run(input, output)?;
+    Ok(())
+}
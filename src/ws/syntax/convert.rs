@@ -79,6 +79,40 @@ pub fn integer_from_unsigned_bits(bits: &BitSlice) -> Integer {
     }
 }
 
+/// Converts bits to an integer like [`integer_from_signed_bits`], but one bit
+/// at a time. [`integer_from_signed_bits`] instead passes the bits to GMP as
+/// whole limbs, which is much faster for large operands, so this is the
+/// reference for it in tests.
+#[cfg(test)]
+#[must_use]
+pub fn integer_from_signed_bits_naive(bits: &BitSlice) -> Integer {
+    match bits.split_first() {
+        None => Integer::ZERO,
+        Some((sign, bits)) => {
+            let mut int = integer_from_unsigned_bits_naive(bits);
+            if *sign {
+                int.neg_assign();
+            }
+            int
+        }
+    }
+}
+
+/// Converts bits to an integer like [`integer_from_unsigned_bits`], but one
+/// bit at a time.
+#[cfg(test)]
+#[must_use]
+pub fn integer_from_unsigned_bits_naive(bits: &BitSlice) -> Integer {
+    let mut int = Integer::with_capacity(bits.len());
+    for bit in bits {
+        int <<= 1;
+        if *bit {
+            int += 1;
+        }
+    }
+    int
+}
+
 #[inline]
 #[must_use]
 pub fn integer_from_unsigned_bits_unambiguous(bits: &BitSlice) -> Option<Integer> {
@@ -6,6 +6,7 @@
 // later version. You should have received a copy of the GNU Lesser General
 // Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
 
+use std::error::Error;
 use std::fmt::{self, Display, Formatter};
 use std::intrinsics;
 use std::ops::{Deref, DerefMut};
@@ -48,6 +49,21 @@ pub enum ParseError {
     NoDigits,
 }
 
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidRadix => write!(f, "invalid radix"),
+            ParseError::InvalidDigit { ch, offset } => {
+                write!(f, "invalid digit {ch:?} at offset {offset}")
+            }
+            ParseError::LeadingUnderscore => write!(f, "leading underscore"),
+            ParseError::NoDigits => write!(f, "no digits"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
 impl IntLiteral {
     /// Parses an integer with the given radix. A radix in 2..=36 uses the
     /// case-insensitive alphabet 0-9A-Z, so upper- and lowercase letters are
@@ -212,6 +228,20 @@ impl IntLiteral {
         Ok(IntLiteral { bits, string, int })
     }
 
+    /// The bit representation, with the sign in the first bit (if nonempty)
+    /// and possible leading zeros.
+    #[inline]
+    #[must_use]
+    pub fn bits(&self) -> &BitSlice {
+        &self.bits
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn into_bits(self) -> BitVec {
+        self.bits
+    }
+
     #[inline]
     #[must_use]
     pub fn sign(&self) -> Sign {
@@ -314,6 +344,122 @@ impl Display for IntLiteral {
     }
 }
 
+/// The number of tokens that an integer operand encodes to, without leading
+/// zeros: a sign, the bits of the magnitude, and the terminating `L`. Zero
+/// encodes to only a sign and `L`.
+///
+/// This allows estimating the size of a program without building its
+/// operands.
+#[inline]
+#[must_use]
+pub fn encoded_len(n: &Integer) -> usize {
+    n.significant_bits() as usize + 2
+}
+
+/// Converts a number to the bits of a label, in unsigned big-endian order with
+/// no leading zeros, so `0` is the empty label.
+///
+/// Labels are compared by their exact bits, so leading zeros make a distinct
+/// label: `1` and `01` are different labels. To match labels from a program
+/// that uses fixed-width labels, use [`label_from_u64_width`].
+#[must_use]
+pub fn label_from_u64(n: u64) -> BitVec {
+    let width = (u64::BITS - n.leading_zeros()) as usize;
+    label_from_u64_width(n, width).unwrap()
+}
+
+/// Converts a number to the bits of a label, in unsigned big-endian order,
+/// padded with leading zeros to `width` bits, or returns `None`, if it does
+/// not fit.
+#[must_use]
+pub fn label_from_u64_width(n: u64, width: usize) -> Option<BitVec> {
+    let len = (u64::BITS - n.leading_zeros()) as usize;
+    if len > width {
+        return None;
+    }
+    let mut bits = BitVec::repeat(false, width - len);
+    bits.extend(
+        n.view_bits::<Msb0>()[n.leading_zeros() as usize..]
+            .iter()
+            .by_vals(),
+    );
+    Some(bits)
+}
+
+/// Converts the bits of a label to a number, for display, or returns `None`,
+/// if it does not fit in a `u64`. Leading zeros are ignored, so distinct labels
+/// can map to the same number.
+#[must_use]
+pub fn label_to_u64(bits: &BitSlice) -> Option<u64> {
+    let bits = &bits[bits.leading_zeros()..];
+    if bits.len() > u64::BITS as usize {
+        return None;
+    }
+    Some((bits.iter().by_vals()).fold(0, |n, bit| n << 1 | u64::from(bit)))
+}
+
+/// How the bits of an integer argument encode its value, so that the
+/// interpreter and the assembler can agree on the convention.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum NumberEncoding {
+    /// A sign bit, which is `T` for negative, followed by the magnitude, in
+    /// big-endian order. This is the standard encoding. The empty argument and
+    /// negative zero are zero.
+    #[default]
+    SignMagnitude,
+    /// Two's complement, in big-endian order, so the first bit has weight
+    /// −2<sup>n−1</sup>. The empty argument is zero.
+    TwosComplement,
+}
+
+impl NumberEncoding {
+    /// Converts the bits of an argument to its value.
+    #[must_use]
+    pub fn decode(self, bits: &BitSlice) -> Integer {
+        match self {
+            NumberEncoding::SignMagnitude => convert::integer_from_signed_bits(bits),
+            NumberEncoding::TwosComplement => {
+                let mut int = convert::integer_from_unsigned_bits(bits);
+                if bits.first().as_deref() == Some(&true) {
+                    int -= Integer::from(1) << bit_width(bits.len());
+                }
+                int
+            }
+        }
+    }
+
+    /// Converts a value to the shortest bits that encode it, which is a
+    /// single `S` for zero.
+    #[must_use]
+    pub fn encode(self, n: &Integer) -> BitVec {
+        match self {
+            NumberEncoding::SignMagnitude => {
+                let sign = if *n < 0 { Sign::Neg } else { Sign::Pos };
+                convert::signed_bits_from_integer(n, sign, 0)
+            }
+            NumberEncoding::TwosComplement => {
+                // The width for -2^k..2^k is k+1 bits, including the sign, and
+                // -n-1, which is !n, has k significant bits.
+                let (magnitude, width) = if *n < 0 {
+                    let width = Integer::from(!n).significant_bits() + 1;
+                    (n.clone() + (Integer::from(1) << width), width as usize)
+                } else {
+                    (n.clone(), n.significant_bits() as usize + 1)
+                };
+                let bits = convert::unsigned_bits_from_integer(&magnitude);
+                let mut padded = BitVec::repeat(false, width - bits.len());
+                padded.extend_from_bitslice(&bits);
+                padded
+            }
+        }
+    }
+}
+
+/// Converts a number of bits to a shift amount.
+fn bit_width(len: usize) -> u32 {
+    u32::try_from(len).expect("argument too long to decode")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -6,14 +6,33 @@
 // later version. You should have received a copy of the GNU Lesser General
 // Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
 
+use std::cell::Cell;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::PathBuf;
+
 use bitvec::prelude::*;
+use rug::Integer;
 
+use crate::syntax::{TokenSeq, Tokens, VariantIndex};
 use crate::text::EncodingError;
-use crate::ws::inst::{Inst, RawInst};
-use crate::ws::parse::Parser;
+use crate::token_seq;
+use crate::ws::inst::{Inst, InstArg, InstError, Opcode, RawInst};
+use crate::ws::parse::{
+    build_table, parse_packed, parse_packed_padded, parse_prefix, parse_take, Limit, ParseError,
+    ParseLimits, Parser, TableError, TABLE,
+};
+use crate::ws::syntax::{self, convert, NumberEncoding, Sign};
 use crate::ws::token::{
-    bit_pack_padded, bit_unpack_padded, Lexer, Mapping, MappingLexer, Token, Token::*,
+    self, bit_pack_container, bit_pack_lsb, bit_pack_msb, bit_pack_padded, bit_unpack_lsb,
+    bit_unpack_msb, bit_unpack_padded, bit_unpack_validated, BitPacker, BitUnpackError, Lexer,
+    LexerExt, Mapping, MappingLexer, StrMappingLexer, Token, Token::*, TokenVec, VerifyError,
 };
+use crate::ws::vm::{RuntimeError, Vm, VmOptions};
+use crate::ws::{assembly, emit_canonical, program, run_file, WsError};
 
 const TUTORIAL_STL: &[u8] = br"
 S S S T L                    push 1
@@ -68,10 +87,34 @@ fn get_tutorial_insts() -> Vec<RawInst> {
     ]
 }
 
+/// Encodes an integer argument in the fewest bits, for tests across modules.
+pub(crate) fn int_arg<N: Into<Integer>>(n: N) -> BitVec {
+    let n = n.into();
+    let sign = if n < 0 { Sign::Neg } else { Sign::Pos };
+    convert::signed_bits_from_integer(&n, sign, 0)
+}
+
+/// Pushes an integer, encoded as by [`int_arg`].
+pub(crate) fn push<N: Into<Integer>>(n: N) -> RawInst {
+    Inst::Push(int_arg(n))
+}
+
+/// Pseudo-random numbers from a linear congruential generator, so that
+/// randomized tests are reproducible.
+pub(crate) fn lcg() -> impl FnMut() -> u64 {
+    let mut state = 1u64;
+    move || {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1);
+        state
+    }
+}
+
 #[test]
 fn lex() -> Result<(), EncodingError> {
     let lex = MappingLexer::new_utf8(TUTORIAL_STL, Mapping::<char>::STL, true);
-    let toks = lex.collect::<Result<Vec<_>, EncodingError>>()?;
+    let toks = lex.collect_tokens()?;
     assert_eq!(TUTORIAL_TOKENS, toks);
     Ok(())
 }
@@ -79,8 +122,67 @@ fn lex() -> Result<(), EncodingError> {
 #[test]
 fn byte_lex() -> Result<(), EncodingError> {
     let lex = MappingLexer::new_bytes(TUTORIAL_STL, Mapping::<u8>::STL);
-    let toks = lex.collect::<Result<Vec<_>, EncodingError>>()?;
+    let toks = lex.collect_tokens()?;
+    assert_eq!(TUTORIAL_TOKENS, toks);
+
+    // Bytes are not decoded, so invalid UTF-8 is ignored like other comments.
+    let lex = MappingLexer::new_bytes(b"S\xffT\xe2\x82L", Mapping::<u8>::STL);
+    assert_eq!(vec![S, T, L], lex.collect_tokens()?);
+    Ok(())
+}
+
+#[test]
+fn lazy_lex_parse() {
+    // A program of 2^18 `push 1` and `drop` pairs, which is generated as it is
+    // read, so the test only holds memory for the instruction being parsed.
+    const PAIRS: usize = 1 << 18;
+    const PAIR: &[u8] = b"SSSTLSLL";
+    let pulled = Cell::new(0);
+    let src = (PAIR.iter().copied().cycle().take(PAIR.len() * PAIRS))
+        .inspect(|_| pulled.set(pulled.get() + 1))
+        .map(Ok);
+    let mut parser = Parser::new(MappingLexer::new(src, Mapping::<u8>::STL));
+    let mut consumed = 0;
+    for i in 0..PAIRS * 2 {
+        let inst = parser.next().unwrap();
+        consumed += inst.token_len();
+        assert_eq!(
+            if i % 2 == 0 {
+                Opcode::Push
+            } else {
+                Opcode::Drop
+            },
+            inst.opcode()
+        );
+        // The lexer reads no further ahead than the end of the instruction.
+        assert_eq!(consumed, pulled.get());
+    }
+    assert!(parser.next().is_none());
+}
+
+#[test]
+fn count_tokens() {
+    let lex = MappingLexer::new_bytes(TUTORIAL_STL, Mapping::<u8>::STL);
+    assert_eq!(Ok(TUTORIAL_TOKENS.len()), lex.count_tokens());
+    let lex = MappingLexer::new_utf8(b"S T\xffL", Mapping::<char>::STL, true);
+    assert!(lex.count_tokens().is_err());
+}
+
+#[test]
+fn str_lex() -> Result<(), EncodingError> {
+    let map = Mapping::new("space", "tab", "lf").unwrap();
+    let mut src = String::new();
+    for &tok in TUTORIAL_TOKENS {
+        src.push_str(map.map_token(tok));
+        src.push_str(if tok == L { "\n" } else { " " });
+    }
+    let toks = StrMappingLexer::new(&src, map).collect_tokens()?;
     assert_eq!(TUTORIAL_TOKENS, toks);
+
+    // The longest lexeme is matched, when lexemes overlap.
+    let map = Mapping::new("a", "ab", "abc").unwrap();
+    let toks = StrMappingLexer::new("abcab\u{3bb}a ba", map);
+    assert_eq!(vec![L, T, S, S], toks.collect_tokens()?);
     Ok(())
 }
 
@@ -95,15 +197,129 @@ fn bit_pack() -> Result<(), EncodingError> {
 fn bit_unpack() -> Result<(), EncodingError> {
     let toks = bit_unpack_padded::<u8, Msb0>(TUTORIAL_BITS);
     assert_eq!(TUTORIAL_TOKENS, toks);
+    let insts = get_tutorial_insts();
+    token::verify_packed(TUTORIAL_BITS, &insts).unwrap_or_else(|err| panic!("{err}"));
+
+    let err = token::verify_packed(TUTORIAL_BITS, &insts[..3]).unwrap_err();
+    let expected = VerifyError::Mismatch {
+        index: 3,
+        actual: Some(insts[3].clone()),
+        expected: None,
+    };
+    assert_eq!(expected, err);
+    let mut changed = insts.clone();
+    changed[1] = Inst::Push(bitvec![0, 1, 0]);
+    let err = token::verify_packed(TUTORIAL_BITS, &changed).unwrap_err();
+    assert_eq!(
+        "at instruction 1: decoded `label .01000011`, but expected `push 2`",
+        err.to_string()
+    );
+    let err = token::verify_packed(&TUTORIAL_BITS[..4], &insts).unwrap_err();
+    assert!(matches!(err, VerifyError::Unpack(_)));
     Ok(())
 }
 
+#[test]
+fn token_vec_bit_pack() {
+    let toks = TUTORIAL_TOKENS.to_vec();
+    assert_eq!(TUTORIAL_BITS, toks.bit_pack::<u8, Msb0>());
+    assert_eq!(toks, Vec::<Token>::bit_unpack::<u8, Msb0>(TUTORIAL_BITS));
+    let words = toks.bit_pack::<u32, Lsb0>();
+    assert_eq!(toks, Vec::<Token>::bit_unpack::<u32, Lsb0>(&words));
+}
+
+#[test]
+fn pack_instructions() {
+    let insts = get_tutorial_insts();
+    assert_eq!(
+        bit_pack_msb(TUTORIAL_TOKENS),
+        token::pack_instructions(&insts)
+    );
+    assert_eq!(
+        bit_pack_msb(TUTORIAL_TOKENS),
+        token::pack_instructions(insts)
+    );
+}
+
+#[test]
+fn bit_pack_named_orders() {
+    assert_eq!(TUTORIAL_BITS, bit_pack_msb(TUTORIAL_TOKENS));
+    assert_eq!(TUTORIAL_TOKENS, bit_unpack_msb(TUTORIAL_BITS));
+    let lsb = bit_pack_lsb(TUTORIAL_TOKENS);
+    assert_ne!(TUTORIAL_BITS, lsb);
+    assert_eq!(TUTORIAL_TOKENS, bit_unpack_lsb(&lsb));
+}
+
+#[test]
+fn parse_bits() {
+    let insts = get_tutorial_insts();
+    assert_eq!(insts, parse_packed_padded::<u8, Msb0>(TUTORIAL_BITS));
+    let bits = token::bit_pack::<u32, Lsb0>(TUTORIAL_TOKENS);
+    assert_eq!(insts, parse_packed(&bits));
+
+    // A truncated program parses the same as after unpacking.
+    let truncated = &TUTORIAL_BITS[..TUTORIAL_BITS.len() / 2];
+    let toks = bit_unpack_padded::<u8, Msb0>(truncated);
+    let expected = Parser::new(toks.into_iter().map(Ok)).collect::<Vec<_>>();
+    assert!(matches!(expected.last(), Some(Inst::Error(_))));
+    assert_eq!(expected, parse_packed_padded::<u8, Msb0>(truncated));
+}
+
+#[test]
+fn bit_pack_map() {
+    let (bytes, ranges) = token::bit_pack_with_map(TUTORIAL_TOKENS);
+    assert_eq!(TUTORIAL_BITS, bytes);
+    assert_eq!(TUTORIAL_TOKENS.len(), ranges.len());
+    let bits = bytes.view_bits::<Msb0>();
+    let mut offset = 0;
+    for (&tok, range) in TUTORIAL_TOKENS.iter().zip(ranges) {
+        assert_eq!(offset, range.start);
+        assert_eq!(vec![tok], token::bit_unpack(&bits[range.clone()]));
+        offset = range.end;
+    }
+}
+
+#[test]
+fn bit_unpack_validated_complete() {
+    let toks = bit_unpack_validated::<u8, Msb0>(TUTORIAL_BITS);
+    assert_eq!(Ok(TUTORIAL_TOKENS.to_vec()), toks);
+}
+
+#[test]
+fn bit_unpack_validated_truncated() {
+    // Truncate `end` to `L L`
+    let end = TUTORIAL_TOKENS.len() - 3;
+    let bits = bit_pack_padded::<u8, Msb0>(&TUTORIAL_TOKENS[..end + 2]);
+    let offset = token::bit_pack::<u8, Msb0>(&TUTORIAL_TOKENS[..end]).len() / 8;
+    let err = BitUnpackError {
+        offset,
+        err: ParseError::IncompleteInst((&[L, L]).into(), vec![
+            Opcode::End,
+            Opcode::DumpStack,
+            Opcode::DumpHeap,
+            Opcode::DumpTrace,
+        ]),
+    };
+    assert_eq!(Err(err), bit_unpack_validated::<u8, Msb0>(&bits));
+}
+
 #[test]
 fn parse() {
     let lex = MappingLexer::new_utf8(TUTORIAL_STL, Mapping::<char>::STL, true);
     let parser = Parser::new(lex);
     let insts = parser.collect::<Vec<_>>();
-    assert_eq!(get_tutorial_insts(), insts);
+    assert_insts_eq(&get_tutorial_insts(), &insts);
+}
+
+/// Asserts that instructions are equal, printing them as listings when not.
+#[track_caller]
+fn assert_insts_eq(expected: &[RawInst], actual: &[RawInst]) {
+    assert!(
+        expected == actual,
+        "instructions differ\nexpected:\n{}actual:\n{}",
+        program::format_listing(expected),
+        program::format_listing(actual),
+    );
 }
 
 #[test]
@@ -124,6 +340,769 @@ fn parse_dyn() {
     for lex in lexers {
         let parser = Parser::new(lex);
         let insts = parser.collect::<Vec<_>>();
-        assert_eq!(get_tutorial_insts(), insts);
+        assert_insts_eq(&get_tutorial_insts(), &insts);
+    }
+}
+
+#[test]
+fn run() {
+    let mut vm = Vm::new(get_tutorial_insts());
+    let output = vm.run_capture(b"").unwrap();
+    assert_eq!(b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n", &output[..]);
+    assert!(vm.is_halted());
+}
+
+#[test]
+fn ws_error() -> Result<(), WsError> {
+    fn run_src(src: &[u8], map: Mapping<u8>) -> Result<Vec<u8>, WsError> {
+        let lex = MappingLexer::new_bytes(src, map);
+        let mut insts = Vec::new();
+        for inst in Parser::new(lex) {
+            if let Inst::Error(err) = inst {
+                return Err(err.into());
+            }
+            insts.push(inst);
+        }
+        Ok(Vm::new(insts).run_capture(b"")?)
+    }
+
+    assert_eq!(
+        b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n",
+        &run_src(TUTORIAL_STL, Mapping::<u8>::STL)?[..]
+    );
+
+    let err = run_src(b"\t\n\t", Mapping::<u8>::default()).unwrap_err();
+    assert!(matches!(
+        err,
+        WsError::Parse(ParseError::IncompleteInst(..))
+    ));
+    assert!(err
+        .to_string()
+        .starts_with("parse error: incomplete opcode `TLT` at EOF"));
+
+    let err = run_src(b"\t \t\t\n\n\n", Mapping::<u8>::default()).unwrap_err();
+    assert_eq!(
+        "runtime error: at instruction 0: stack underflow: needed 2 values, but the stack had 0",
+        err.to_string(),
+    );
+
+    let err = WsError::from(RuntimeError::Io(io::ErrorKind::UnexpectedEof.into()));
+    let source = err.source().unwrap();
+    assert!(source.downcast_ref::<io::Error>().is_some());
+    Ok(())
+}
+
+#[test]
+fn run_file_detects_format() -> Result<(), WsError> {
+    /// Removes the directory when dropped, even when an assertion fails.
+    struct TempDir(PathBuf);
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    let temp = TempDir(env::temp_dir().join(format!("nebula2-run-file-{}", std::process::id())));
+    let dir = &temp.0;
+    fs::create_dir_all(dir)?;
+    let run = |name: &str, contents: &[u8]| -> Result<Vec<u8>, WsError> {
+        let path = dir.join(name);
+        fs::write(&path, contents)?;
+        let mut output = Vec::new();
+        run_file(&path, Mapping::<u8>::STL, &mut &b""[..], &mut output)?;
+        Ok(output)
+    };
+
+    let expected = b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+    assert_eq!(expected, &run("tutorial.ws", TUTORIAL_STL)?[..]);
+    let toks = MappingLexer::new_bytes(TUTORIAL_STL, Mapping::<u8>::STL).collect_tokens()?;
+    let container = bit_pack_container(&toks);
+    assert_eq!(expected, &run("tutorial.wsx", &container)?[..]);
+
+    // Content without the magic header is source, even if it is binary, so
+    // this has no instructions.
+    let err = run("binary.ws", &container[1..]).unwrap_err();
+    assert!(
+        matches!(&err, WsError::Runtime(err) if matches!(err.kind(), RuntimeError::NoEnd)),
+        "{err:?}"
+    );
+    let err = run("truncated.wsx", &bit_pack_container(&[T, L])).unwrap_err();
+    assert!(matches!(
+        err,
+        WsError::BitUnpack(BitUnpackError { offset: 4, .. })
+    ));
+    assert!(matches!(
+        run_file(
+            dir.join("none"),
+            Mapping::<u8>::STL,
+            &mut &b""[..],
+            &mut Vec::new()
+        ),
+        Err(WsError::Io(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn integer_from_bits() {
+    let mut rand = lcg();
+    let mut bits = BitVec::with_capacity(5000);
+    for _ in 0..5000 {
+        bits.push(rand() >> 63 != 0);
+    }
+    for len in (0..300).chain([1000, 4999, 5000]) {
+        let bits = &bits[..len];
+        assert_eq!(
+            convert::integer_from_signed_bits_naive(bits),
+            convert::integer_from_signed_bits(bits),
+            "len {len}",
+        );
+        assert_eq!(
+            convert::integer_from_unsigned_bits_naive(bits),
+            convert::integer_from_unsigned_bits(bits),
+            "len {len}",
+        );
+    }
+}
+
+#[test]
+fn stack_model() {
+    let mut lcg = lcg();
+    let mut rand = |n: usize| usize::try_from(lcg() >> 33).unwrap() % n;
+
+    for _ in 0..50 {
+        let mut vm = Vm::new(Vec::new());
+        let mut model: Vec<Integer> = Vec::new();
+        for _ in 0..200 {
+            let len = model.len();
+            let inst = match rand(6) {
+                1 if len >= 1 => {
+                    model.push(model[len - 1].clone());
+                    Inst::Dup
+                }
+                2 if len >= 2 => {
+                    model.swap(len - 1, len - 2);
+                    Inst::Swap
+                }
+                3 if len >= 1 => {
+                    model.pop();
+                    Inst::Drop
+                }
+                4 if len >= 1 => {
+                    let n = rand(len);
+                    model.push(model[len - 1 - n].clone());
+                    Inst::Copy(int_arg(n))
+                }
+                5 if len >= 1 => {
+                    let n = rand(len);
+                    let top = model.pop().unwrap();
+                    model.truncate(len - 1 - n);
+                    model.push(top);
+                    Inst::Slide(int_arg(n))
+                }
+                _ => {
+                    let n = Integer::from(rand(2001)) - 1000;
+                    let inst = push(&n);
+                    model.push(n);
+                    inst
+                }
+            };
+            vm.exec_one(&inst).unwrap();
+            assert_eq!(&model[..], vm.stack(), "after {inst}");
+        }
+    }
+}
+
+#[test]
+fn token_all() {
+    let all = Token::all();
+    assert_eq!(Token::COUNT as usize, all.len());
+    for (i, tok) in all.into_iter().enumerate() {
+        assert_eq!(i as u32, tok.index());
+        assert_eq!(i as u8, tok.to_u8());
+        assert_eq!(Some(tok), Token::from_u8(tok.to_u8()));
+    }
+    assert_eq!(None, Token::from_u8(3));
+    assert_eq!(None, Token::from_u8(u8::MAX));
+}
+
+#[test]
+fn const_token_seq() {
+    // The prefixes of each instruction modification parameter.
+    const IMPS: [TokenSeq<Token>; 5] = [
+        token_seq!(S),
+        token_seq!(T S),
+        token_seq!(T T),
+        token_seq!(L),
+        token_seq!(T L),
+    ];
+    assert_eq!(TokenSeq::new(), token_seq!());
+    assert_eq!(TokenSeq::from(&[T, S]), IMPS[1]);
+    for opcode in Opcode::iter() {
+        let prefixes = IMPS.iter().filter(|imp| {
+            let toks: Vec<Token> = (**imp).into();
+            opcode.tokens().starts_with(&toks)
+        });
+        assert_eq!(1, prefixes.count(), "{opcode}");
+    }
+}
+
+#[test]
+fn table_covers_opcodes() {
+    for opcode in Opcode::iter() {
+        let mut lex = opcode.tokens().iter().copied().map(Ok);
+        assert_eq!(Some(Ok(opcode)), TABLE.parse(&mut lex), "{opcode}");
+    }
+}
+
+#[test]
+fn table_prefix_free() {
+    for a in Opcode::iter() {
+        for b in Opcode::iter() {
+            if a != b {
+                assert!(!a.tokens().starts_with(b.tokens()), "{a} starts with {b}");
+            }
+        }
+    }
+    assert!(build_table(Opcode::iter().map(|op| (op.tokens(), op))).is_ok());
+
+    // Every sequence of tokens starts with at most one opcode.
+    let mut seqs = vec![Vec::new()];
+    for _ in 0..4 {
+        seqs = (seqs.iter())
+            .flat_map(|seq| Token::all().map(|tok| [&seq[..], &[tok]].concat()))
+            .collect();
+        for seq in &seqs {
+            let matches = Opcode::iter().filter(|op| seq.starts_with(op.tokens()));
+            assert!(matches.count() <= 1, "{seq:?}");
+        }
+    }
+
+    let table = [(&[S, S][..], Opcode::Push), (&[S, S, T][..], Opcode::Dup)];
+    let conflict = TableError::PrefixConflict { a: Opcode::Push, b: Opcode::Dup };
+    assert_eq!(Some(conflict), build_table(table).err());
+    let table = [(&[S, S, T][..], Opcode::Dup), (&[S, S][..], Opcode::Push)];
+    let conflict = TableError::PrefixConflict { a: Opcode::Dup, b: Opcode::Push };
+    assert_eq!(Some(conflict), build_table(table).err());
+}
+
+#[test]
+fn emit() {
+    let insts = get_tutorial_insts();
+    let src = emit_canonical(&insts);
+    assert!(src.chars().all(|ch| matches!(ch, ' ' | '\t' | '\n')));
+    let lex = MappingLexer::new_utf8(&src, Mapping::<char>::default(), true);
+    assert_eq!(insts, Parser::new(lex).collect::<Vec<_>>());
+}
+
+#[test]
+fn parse_trailing() {
+    let toks = TUTORIAL_TOKENS.iter().chain(&[T, T, L, S]).copied();
+    let (insts, len) = parse_prefix(toks.map(Ok));
+    assert_eq!(get_tutorial_insts(), insts);
+    assert_eq!(TUTORIAL_TOKENS.len(), len);
+
+    let (insts, len) = parse_prefix(TUTORIAL_TOKENS[..5].iter().copied().map(Ok));
+    assert_eq!(vec![Inst::Push(bitvec![0, 1])], insts);
+    assert_eq!(5, len);
+}
+
+#[test]
+fn parse_take_prefix() {
+    let insts = get_tutorial_insts();
+    let mut lex = TUTORIAL_TOKENS.iter().copied().map(Ok);
+    assert_eq!(&insts[..3], parse_take(&mut lex, 3));
+    assert_eq!(&insts[3..5], parse_take(&mut lex, 2));
+    assert_eq!(&insts[5..], parse_take(&mut lex, usize::MAX));
+    assert!(parse_take(TUTORIAL_TOKENS.iter().copied().map(Ok), 0).is_empty());
+}
+
+#[test]
+fn int_encoded_len() {
+    for n in [0, 1, -1, 2, 7, -8, 255, 256, -65_537, i64::MAX, i64::MIN] {
+        let n = Integer::from(n);
+        let bits = int_arg(&n);
+        assert_eq!(bits.len() + 1, syntax::encoded_len(&n), "{n}");
+        let push = Inst::Push(bits);
+        assert_eq!(
+            push.token_len(),
+            Opcode::Push.tokens().len() + syntax::encoded_len(&n)
+        );
+    }
+    let big = Integer::from(1) << 1000;
+    assert_eq!(1003, syntax::encoded_len(&big));
+}
+
+#[test]
+fn label_round_trip() {
+    // Labels that are empty, have leading zeros, or are followed by
+    // instructions starting with `L`, which must not be read into the label.
+    let labels = [
+        bitvec![],
+        bitvec![0],
+        bitvec![1],
+        bitvec![0, 0, 0],
+        bitvec![1, 1, 1, 1],
+        bitvec![0, 1, 0, 1, 1],
+    ];
+    let mut insts = Vec::new();
+    for label in &labels {
+        insts.push(Inst::Label(label.clone()));
+        insts.push(Inst::Jmp(label.clone()));
+        insts.push(Inst::Ret);
+        insts.push(Inst::Call(label.clone()));
+        insts.push(Inst::End);
+    }
+    let mut toks = Vec::new();
+    for inst in &insts {
+        let start = toks.len();
+        inst.encode(&mut toks);
+        if let Inst::Label(label) | Inst::Jmp(label) | Inst::Call(label) = inst {
+            let arg = &toks[start + inst.opcode().tokens().len()..];
+            assert_eq!(label.len() + 1, arg.len());
+            assert_eq!(Some(&L), arg.last());
+            assert!(!arg[..arg.len() - 1].contains(&L));
+        }
+    }
+    assert_eq!(
+        insts,
+        Parser::new(toks.into_iter().map(Ok)).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn label_from_u64() {
+    assert_eq!(bitvec![], syntax::label_from_u64(0));
+    assert_eq!(bitvec![1, 0, 1], syntax::label_from_u64(5));
+    assert_eq!(
+        Some(bitvec![0, 0, 1, 0, 1]),
+        syntax::label_from_u64_width(5, 5)
+    );
+    assert_eq!(None, syntax::label_from_u64_width(5, 2));
+    assert_eq!(64, syntax::label_from_u64(u64::MAX).len());
+    for n in [0, 1, 5, 1 << 40, u64::MAX] {
+        assert_eq!(Some(n), syntax::label_to_u64(&syntax::label_from_u64(n)));
+    }
+    assert_eq!(Some(5), syntax::label_to_u64(bits![0, 0, 1, 0, 1]));
+    let mut long = bitvec![1];
+    long.extend(syntax::label_from_u64_width(0, 64).unwrap());
+    assert_eq!(None, syntax::label_to_u64(&long));
+}
+
+#[test]
+fn number_encoding() {
+    use NumberEncoding::{SignMagnitude, TwosComplement};
+
+    // Negative zero, a sign with an empty magnitude, is zero under the
+    // default, as is the empty argument.
+    assert_eq!(SignMagnitude, NumberEncoding::default());
+    for bits in [bits![], bits![1], bits![1, 0, 0], bits![0]] {
+        assert_eq!(Integer::ZERO, SignMagnitude.decode(bits));
+    }
+    assert_eq!(Integer::ZERO, TwosComplement.decode(bits![]));
+
+    let cases: [(i32, &BitSlice, &BitSlice); 7] = [
+        (0, bits![0], bits![0]),
+        (1, bits![0, 1], bits![0, 1]),
+        (-1, bits![1, 1], bits![1]),
+        (2, bits![0, 1, 0], bits![0, 1, 0]),
+        (-2, bits![1, 1, 0], bits![1, 0]),
+        (-3, bits![1, 1, 1], bits![1, 0, 1]),
+        (-4, bits![1, 1, 0, 0], bits![1, 0, 0]),
+    ];
+    for (n, sign_magnitude, twos_complement) in cases {
+        let n = Integer::from(n);
+        assert_eq!(sign_magnitude, SignMagnitude.encode(&n), "{n}");
+        assert_eq!(twos_complement, TwosComplement.encode(&n), "{n}");
+    }
+    for n in -300..300 {
+        let n = Integer::from(n);
+        for encoding in [SignMagnitude, TwosComplement] {
+            assert_eq!(n, encoding.decode(&encoding.encode(&n)));
+        }
+    }
+    // Sign extension does not change the value.
+    assert_eq!(
+        Integer::from(-3),
+        TwosComplement.decode(bits![1, 1, 1, 0, 1])
+    );
+
+    // The assembler and interpreter agree.
+    let src = "push -5\ndup\nprinti\npush 1\ncopy 1\nprinti\nend\n";
+    let options = VmOptions {
+        number_encoding: TwosComplement,
+        ..VmOptions::default()
+    };
+    let insts = assembly::assemble_with(src, TwosComplement).unwrap();
+    assert_eq!(Inst::Push(bitvec![1, 0, 1, 1]), insts[0]);
+    let disassembly = assembly::disassemble_with(&insts, TwosComplement);
+    assert!(disassembly.starts_with("    push -5\n"));
+    assert_eq!(
+        Ok(insts.clone()),
+        assembly::assemble_with(&disassembly, TwosComplement)
+    );
+    let mut vm = Vm::with_options(insts, options);
+    assert_eq!(b"-5-5", &vm.run_capture(b"").unwrap()[..]);
+}
+
+#[test]
+fn int_round_trip() {
+    // Integers with non-minimal encodings, which have leading zeros or only a
+    // sign, are kept bit-exact.
+    #[rustfmt::skip]
+    let toks = [
+        S, S, S, S, T, L, // push +01
+        S, S, T, S, S, L, // push -00
+        S, S, S, L,       // push +
+        S, S, L,          // push
+        S, T, S, S, S, T, L, // copy +01
+    ];
+    let insts = Parser::new(toks.into_iter().map(Ok)).collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            Inst::Push(bitvec![0, 0, 1]),
+            Inst::Push(bitvec![1, 0, 0]),
+            Inst::Push(bitvec![0]),
+            Inst::Push(bitvec![]),
+            Inst::Copy(bitvec![0, 0, 1]),
+        ],
+        insts,
+    );
+    let mut encoded = Vec::new();
+    for inst in &insts {
+        inst.encode(&mut encoded);
+    }
+    assert_eq!(&toks[..], encoded);
+}
+
+#[test]
+fn encode() {
+    let insts = get_tutorial_insts();
+    let mut toks = Vec::new();
+    let mut packer = BitPacker::<u8, Msb0>::new();
+    for inst in &insts {
+        inst.encode(&mut toks);
+        inst.encode(&mut packer);
+    }
+    assert_eq!(TUTORIAL_TOKENS, toks);
+    assert_eq!(TUTORIAL_BITS, packer.into_padded());
+    let len = insts.iter().map(RawInst::token_len).sum::<usize>();
+    assert_eq!(TUTORIAL_TOKENS.len(), len);
+}
+
+#[test]
+fn lex_crlf() -> Result<(), EncodingError> {
+    let lf = TUTORIAL_TOKENS
+        .iter()
+        .map(|tok| match tok {
+            S => " ",
+            T => "\t",
+            L => "\n",
+        })
+        .collect::<String>();
+    let crlf = lf.replace('\n', "\r\n");
+    let cr = lf.replace('\n', "\r");
+    for src in [&lf, &crlf, &cr] {
+        let lex = MappingLexer::new_utf8(src, Mapping::<char>::default(), true).accept_crlf();
+        assert_eq!(TUTORIAL_TOKENS, lex.collect_tokens()?);
+        let lex = MappingLexer::new_bytes(src, Mapping::<u8>::default()).accept_crlf();
+        assert_eq!(TUTORIAL_TOKENS, lex.collect_tokens()?);
+    }
+    let lex = MappingLexer::new_bytes(&cr, Mapping::<u8>::default());
+    assert_ne!(TUTORIAL_TOKENS, lex.collect_tokens()?);
+    Ok(())
+}
+
+#[test]
+fn parse_error_tokens() {
+    let mut parser = Parser::new([S, S, T, S].into_iter().map(Ok));
+    let err = ParseError::UnterminatedArg(Opcode::Push, bitvec![1, 0]);
+    assert_eq!(Some(Inst::from(err.clone())), parser.next());
+    assert_eq!(vec![S, S, T, S], err.tokens());
+
+    let mut parser = Parser::new([S, T, T, T].into_iter().map(Ok));
+    let err = ParseError::UnknownOpcode((&[S, T, T, T]).into());
+    assert_eq!(Some(Inst::from(err.clone())), parser.next());
+    assert_eq!(vec![S, T, T, T], err.tokens());
+}
+
+#[test]
+fn missing_final_terminator() {
+    let parse = |toks: &[Token], allow| {
+        Parser::new(toks.iter().copied().map(Ok))
+            .allow_missing_final_terminator(allow)
+            .collect::<Vec<_>>()
+    };
+    // The tutorial without the final `L` of `end`.
+    let toks = &TUTORIAL_TOKENS[..TUTORIAL_TOKENS.len() - 1];
+    let strict = parse(toks, false);
+    assert!(matches!(
+        strict.last(),
+        Some(Inst::Error(InstError::ParseError(
+            ParseError::IncompleteInst(..)
+        )))
+    ));
+    assert_insts_eq(&get_tutorial_insts(), &parse(toks, true));
+
+    assert_eq!(vec![Inst::Push(bitvec![0, 1])], parse(&[S, S, S, T], true));
+    assert!(matches!(&parse(&[S, S, S, T], false)[..], [Inst::Error(
+        InstError::ParseError(ParseError::UnterminatedArg(..))
+    )]));
+    // Only one `L` is implied.
+    assert_eq!(vec![Inst::Push(bitvec![])], parse(&[S, S], true));
+    for toks in [&[L][..], &[S], &[T, L]] {
+        assert_eq!(parse(toks, false), parse(toks, true), "{toks:?}");
+    }
+}
+
+#[test]
+fn parse_limits() {
+    // An endless argument stops at the default limit, without allocating
+    // unboundedly.
+    let toks = [S, S].into_iter().chain(iter::repeat(T));
+    let mut parser = Parser::new(toks.map(Ok));
+    let err = ParseError::LimitExceeded(Limit::OperandTokens(Opcode::Push));
+    assert_eq!(Some(Inst::from(err)), parser.next());
+    assert_eq!(None, parser.next());
+
+    let limits = ParseLimits {
+        max_operand_tokens: 3,
+        max_instructions: 2,
+    };
+    let parse = |toks: &[Token]| {
+        Parser::new(toks.iter().copied().map(Ok))
+            .limits(limits)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        vec![Inst::Push(bitvec![0, 1, 1])],
+        parse(&[S, S, S, T, T, L]),
+    );
+    assert_eq!(
+        vec![Inst::from(ParseError::LimitExceeded(Limit::OperandTokens(
+            Opcode::Push
+        )))],
+        parse(&[S, S, S, T, T, S, L]),
+    );
+    assert_eq!(vec![Inst::Dup, Inst::Dup], parse(&[S, L, S, S, L, S]));
+    assert_eq!(
+        vec![
+            Inst::Dup,
+            Inst::Dup,
+            Inst::from(ParseError::LimitExceeded(Limit::Instructions)),
+        ],
+        parse(&[S, L, S, S, L, S, S, L, S, S, L, S]),
+    );
+}
+
+#[test]
+fn opcode_golden() {
+    #[rustfmt::skip]
+    let golden: &[(Opcode, &[Token])] = &[
+        (Opcode::Push, &[S, S]),
+        (Opcode::Dup, &[S, L, S]),
+        (Opcode::Copy, &[S, T, S]),
+        (Opcode::Swap, &[S, L, T]),
+        (Opcode::Drop, &[S, L, L]),
+        (Opcode::Slide, &[S, T, L]),
+        (Opcode::Add, &[T, S, S, S]),
+        (Opcode::Sub, &[T, S, S, T]),
+        (Opcode::Mul, &[T, S, S, L]),
+        (Opcode::Div, &[T, S, T, S]),
+        (Opcode::Mod, &[T, S, T, T]),
+        (Opcode::Store, &[T, T, S]),
+        (Opcode::Retrieve, &[T, T, T]),
+        (Opcode::Label, &[L, S, S]),
+        (Opcode::Call, &[L, S, T]),
+        (Opcode::Jmp, &[L, S, L]),
+        (Opcode::Jz, &[L, T, S]),
+        (Opcode::Jn, &[L, T, T]),
+        (Opcode::Ret, &[L, T, L]),
+        (Opcode::End, &[L, L, L]),
+        (Opcode::Printc, &[T, L, S, S]),
+        (Opcode::Printi, &[T, L, S, T]),
+        (Opcode::Readc, &[T, L, T, S]),
+        (Opcode::Readi, &[T, L, T, T]),
+        (Opcode::Shuffle, &[S, T, T, S]),
+        (Opcode::DumpStack, &[L, L, S, S, S]),
+        (Opcode::DumpHeap, &[L, L, S, S, T]),
+        (Opcode::DumpTrace, &[L, L, T]),
+    ];
+    assert_eq!(Opcode::COUNT as usize, golden.len());
+    for (i, &(opcode, prefix)) in golden.iter().enumerate() {
+        assert_eq!(opcode, Opcode::variant(i as u32));
+        assert_eq!(prefix, opcode.tokens(), "prefix of {opcode}");
+
+        let inst: RawInst = Inst::from(opcode).map_arg(|_, arg| {
+            Ok::<_, ParseError>(match arg {
+                InstArg::Int(()) => InstArg::Int(bitvec![1, 0, 1]),
+                InstArg::Label(()) => InstArg::Label(bitvec![0, 1]),
+            })
+        });
+        let mut toks = Vec::new();
+        inst.encode(&mut toks);
+        assert!(toks.starts_with(prefix), "encoding of {opcode}");
+
+        let src = toks
+            .iter()
+            .map(|&tok| *Mapping::<char>::STL.map_token(tok))
+            .collect::<String>();
+        let lex = MappingLexer::new_utf8(&src, Mapping::<char>::STL, true);
+        assert_eq!(
+            vec![inst],
+            Parser::new(lex).collect::<Vec<_>>(),
+            "round trip of {src}"
+        );
+    }
+}
+
+#[test]
+fn assemble() {
+    let src = "
+        ; Count from 1 to 10
+        push 1
+    .01000011:          ; label_C
+        dup
+        printi          ; print the counter
+        push 10
+        printc
+
+        push 1 ; increment
+        add
+        dup
+        push 11
+        sub
+        jz .01000101
+        jmp .01000011
+    .01000101:
+        drop
+        end
+    ";
+    assert_eq!(Ok(get_tutorial_insts()), assembly::assemble(src));
+    let disassembly = assembly::disassemble(&get_tutorial_insts());
+    assert_eq!(Ok(get_tutorial_insts()), assembly::assemble(&disassembly));
+
+    let insts = vec![
+        Inst::Push(bitvec![]),
+        Inst::Push(bitvec![1]),
+        Inst::Push(bitvec![1, 0]),
+        Inst::Push(bitvec![0, 0, 1]),
+        Inst::Push(bitvec![1, 1, 1]),
+        Inst::Label(bitvec![]),
+        Inst::Call(bitvec![1, 0]),
+        Inst::DumpStack,
+    ];
+    assert_eq!(
+        Ok(insts.clone()),
+        assembly::assemble(&assembly::disassemble(&insts))
+    );
+}
+
+#[test]
+fn assemble_named_labels() {
+    let insts = assembly::assemble("start: jmp 1\njmp start ; named\n1:").unwrap();
+    assert_eq!(
+        vec![
+            Inst::Label(bitvec![]),
+            Inst::Jmp(bitvec![1]),
+            Inst::Jmp(bitvec![]),
+            Inst::Label(bitvec![1]),
+        ],
+        insts,
+    );
+    assert_eq!(
+        Err(assembly::AssembleError::MissingArg { line: 2, opcode: Opcode::Push }),
+        assembly::assemble("dup\npush\n"),
+    );
+}
+
+#[test]
+fn assemble_tokens() {
+    let insts = assembly::assemble("start: .tokens SSSTL LSLTL ; raw\njmp start").unwrap();
+    let [Inst::Label(start), Inst::Push(n), Inst::Jmp(raw), Inst::Jmp(start2)] = &insts[..] else {
+        panic!("unexpected instructions: {insts:?}");
+    };
+    assert_eq!((&bitvec![0, 1], &bitvec![1]), (n, raw));
+    assert_eq!(start, start2);
+    assert_ne!(start, raw);
+
+    assert_eq!(
+        Err(assembly::AssembleError::UnexpectedToken { line: 1, token: "STx".into() }),
+        assembly::assemble(".tokens STx"),
+    );
+    assert!(matches!(
+        assembly::assemble("dup\n.tokens SST"),
+        Err(assembly::AssembleError::InvalidTokens {
+            line: 2,
+            err: ParseError::UnterminatedArg(Opcode::Push, _),
+        }),
+    ));
+}
+
+#[test]
+fn io_signature() {
+    let sig = program::io_signature(&get_tutorial_insts());
+    let expected = program::IoSignature {
+        readc: 0,
+        readi: 0,
+        printc: 1,
+        printi: 1,
+    };
+    assert_eq!(expected, sig);
+    assert!(!sig.reads() && sig.writes());
+}
+
+#[test]
+fn explain() {
+    let lines = program::explain(&get_tutorial_insts());
+    assert_eq!(
+        [
+            "push 1 onto the stack",
+            "mark label .01000011",
+            "duplicate the top of the stack",
+            "pop a value and print it as an integer",
+        ],
+        &lines[..4],
+    );
+    assert_eq!("end the program", lines[lines.len() - 1]);
+}
+
+#[test]
+fn stack_effect() {
+    assert_eq!((2, 1), RawInst::Add.stack_effect());
+    assert_eq!((1, 2), RawInst::Dup.stack_effect());
+    assert_eq!((0, 1), RawInst::Push(bitvec![0, 1]).stack_effect());
+    assert_eq!((2, 0), RawInst::Store.stack_effect());
+    assert_eq!((1, 0), RawInst::Jz(bitvec![]).stack_effect());
+    assert_eq!((4, 5), RawInst::Copy(bitvec![0, 1, 1]).stack_effect());
+    assert_eq!((4, 1), RawInst::Slide(bitvec![0, 1, 1]).stack_effect());
+    assert_eq!(
+        (usize::MAX, 1),
+        RawInst::Slide(bitvec![1, 1]).stack_effect()
+    );
+}
+
+#[test]
+fn bit_string() {
+    let s = token::to_bit_string(TUTORIAL_TOKENS);
+    assert!(s.starts_with("0b00010111, 0b10001000, 0b00101011,"));
+    assert!(s.ends_with(", 0b11111100"));
+    assert_eq!(Some(TUTORIAL_TOKENS.to_vec()), token::from_bit_string(&s));
+    assert_eq!(
+        bit_unpack_msb(TUTORIAL_BITS),
+        token::from_bit_string(&s).unwrap()
+    );
+    assert_eq!(None, token::from_bit_string("0b00010111, 0b2"));
+}
+
+#[test]
+fn token_histogram() {
+    let mut expected = [0; 3];
+    for &tok in TUTORIAL_TOKENS {
+        expected[tok as usize] += 1;
     }
+    assert_eq!(
+        expected,
+        token::token_histogram(TUTORIAL_STL, Mapping::<u8>::STL)
+    );
 }
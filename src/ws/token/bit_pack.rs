@@ -8,12 +8,22 @@
 
 //! Routines to pack and unpack tokens using a compact bitwise encoding.
 
+use std::borrow::Borrow;
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt::{self, Formatter};
+use std::iter::FusedIterator;
 use std::mem::size_of;
+use std::ops::Range;
 
 use bitvec::prelude::*;
 use strum::{Display, EnumString};
 
-use crate::ws::token::Token;
+use crate::ws::inst::{Inst, InstError, RawInst};
+use crate::ws::parse::{ParseError, Parser};
+use crate::ws::program::InstText;
+use crate::ws::syntax::NumberEncoding;
+use crate::ws::token::{BitPacker, Token, TokenSink};
 
 /// Packs tokens into a compact bitwise encoding.
 ///
@@ -50,21 +60,11 @@ use crate::ws::token::Token;
 #[must_use]
 pub fn bit_pack<T: BitStore, O: BitOrder>(toks: &[Token]) -> BitVec<T, O> {
     // TODO: Survey programs to find better size ratio estimate.
-    let mut bits = BitVec::with_capacity(toks.len() * 2);
+    let mut packer = BitPacker::<T, O>::with_capacity(toks.len());
     for &tok in toks {
-        match tok {
-            Token::S => bits.push(false),
-            Token::T => {
-                bits.push(true);
-                bits.push(false);
-            }
-            Token::L => {
-                bits.push(true);
-                bits.push(true);
-            }
-        }
+        packer.push(tok);
     }
-    bits
+    packer.into_bits()
 }
 
 /// Unpacks tokens from a compact bitwise encoding.
@@ -77,33 +77,65 @@ pub fn bit_unpack<T: BitStore, O: BitOrder>(bits: &BitSlice<T, O>) -> Vec<Token>
     // TODO: Survey programs to find better size ratio estimate.
     // TODO: Use TokenVec here, once it can extend its capacity.
     let mut toks = Vec::with_capacity(bits.len());
-    let mut bits = bits.into_iter();
-    loop {
-        toks.push(match bits.next().as_deref() {
-            Some(true) => match bits.next().as_deref() {
-                Some(true) => Token::L,
-                Some(false) => Token::T,
-                None => break, // Marker bit
+    toks.extend(BitUnpacker::new(bits));
+    toks
+}
+
+/// An iterator, that lazily unpacks tokens from a compact bitwise encoding.
+///
+/// See [`bit_unpack`].
+#[derive(Clone, Debug)]
+pub struct BitUnpacker<'a, T: BitStore, O: BitOrder> {
+    bits: bitvec::slice::Iter<'a, T, O>,
+}
+
+impl<'a, T: BitStore, O: BitOrder> BitUnpacker<'a, T, O> {
+    /// Constructs an unpacker for bits without padding, as by
+    /// [`bit_unpack`].
+    #[inline]
+    #[must_use]
+    pub fn new(bits: &'a BitSlice<T, O>) -> Self {
+        BitUnpacker { bits: bits.iter() }
+    }
+
+    /// Constructs an unpacker for bits padded with trailing bits, as by
+    /// [`bit_unpack_padded`].
+    #[inline]
+    #[must_use]
+    pub fn new_padded(bits: &'a [T]) -> Self {
+        BitUnpacker::new(trim_padding(BitSlice::from_slice(bits)))
+    }
+}
+
+impl<T: BitStore, O: BitOrder> Iterator for BitUnpacker<'_, T, O> {
+    type Item = Token;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.bits.next().as_deref() {
+            Some(true) => match self.bits.next().as_deref() {
+                Some(true) => Some(Token::L),
+                Some(false) => Some(Token::T),
+                None => None, // Marker bit
             },
-            Some(false) => Token::S,
-            None => break, // EOF
-        });
+            Some(false) => Some(Token::S),
+            None => None, // EOF
+        }
     }
-    toks
 }
 
+impl<T: BitStore, O: BitOrder> FusedIterator for BitUnpacker<'_, T, O> {}
+
 /// Packs tokens into a compact bitwise encoding, padded with trailing bits.
 ///
 /// See [`bit_pack`] for a description of the encoding.
 #[must_use]
 pub fn bit_pack_padded<T: BitStore, O: BitOrder>(toks: &[Token]) -> Vec<T> {
-    let mut bits = bit_pack::<T, O>(toks);
-    // Follow a final `0` bit with a marker `1` bit to avoid ambiguity.
-    if bits.last().as_deref() == Some(&false) {
-        bits.push(true);
+    let mut packer = BitPacker::<T, O>::with_capacity(toks.len());
+    for &tok in toks {
+        packer.push(tok);
     }
-    bits.set_uninitialized(false);
-    bits.into_vec()
+    packer.into_padded()
 }
 
 /// Unpacks tokens from a compact bitwise encoding, padded with trailing bits.
@@ -111,13 +143,278 @@ pub fn bit_pack_padded<T: BitStore, O: BitOrder>(toks: &[Token]) -> Vec<T> {
 /// See [`bit_pack`] for a description of the encoding.
 #[must_use]
 pub fn bit_unpack_padded<T: BitStore, O: BitOrder>(bits: &[T]) -> Vec<Token> {
-    let mut bits = BitSlice::<T, O>::from_slice(bits);
-    // Trim trailing zeros in the last element.
+    bit_unpack(trim_padding(BitSlice::<T, O>::from_slice(bits)))
+}
+
+/// Trims the trailing zeros in the last element.
+fn trim_padding<T: BitStore, O: BitOrder>(bits: &BitSlice<T, O>) -> &BitSlice<T, O> {
     let tz = bits.trailing_zeros();
     if 0 < tz && tz <= size_of::<T>() * 8 {
-        bits = &bits[..bits.len() - tz];
+        &bits[..bits.len() - tz]
+    } else {
+        bits
+    }
+}
+
+/// Packs tokens into a compact bitwise encoding in bytes with the
+/// most-significant bit first, padded with trailing bits.
+///
+/// This is the bit order of the `TUTORIAL_BITS` test fixture. Bits packed with
+/// this function must be unpacked with [`bit_unpack_msb`].
+///
+/// See [`bit_pack`] for a description of the encoding.
+#[inline]
+#[must_use]
+pub fn bit_pack_msb(toks: &[Token]) -> Vec<u8> {
+    bit_pack_padded::<u8, Msb0>(toks)
+}
+
+/// Unpacks tokens from a compact bitwise encoding in bytes with the
+/// most-significant bit first, padded with trailing bits.
+///
+/// See [`bit_pack_msb`].
+#[inline]
+#[must_use]
+pub fn bit_unpack_msb(bits: &[u8]) -> Vec<Token> {
+    bit_unpack_padded::<u8, Msb0>(bits)
+}
+
+/// Packs tokens into a compact bitwise encoding in bytes with the
+/// least-significant bit first, padded with trailing bits.
+///
+/// Bits packed with this function must be unpacked with [`bit_unpack_lsb`],
+/// not [`bit_unpack_msb`].
+///
+/// See [`bit_pack`] for a description of the encoding.
+#[inline]
+#[must_use]
+pub fn bit_pack_lsb(toks: &[Token]) -> Vec<u8> {
+    bit_pack_padded::<u8, Lsb0>(toks)
+}
+
+/// Unpacks tokens from a compact bitwise encoding in bytes with the
+/// least-significant bit first, padded with trailing bits.
+///
+/// See [`bit_pack_lsb`].
+#[inline]
+#[must_use]
+pub fn bit_unpack_lsb(bits: &[u8]) -> Vec<Token> {
+    bit_unpack_padded::<u8, Lsb0>(bits)
+}
+
+/// Packs the tokens of instructions, as by [`bit_pack_msb`], encoding each
+/// instruction directly into the packer, without collecting the tokens first.
+///
+/// # Panics
+///
+/// Panics if any instruction is an `Error`.
+#[must_use]
+pub fn pack_instructions<I>(insts: I) -> Vec<u8>
+where
+    I: IntoIterator,
+    I::Item: Borrow<RawInst>,
+{
+    let mut packer = BitPacker::<u8, Msb0>::new();
+    for inst in insts {
+        inst.borrow().encode(&mut packer);
+    }
+    packer.into_padded()
+}
+
+/// The range of bit offsets that a token is packed to.
+pub type BitRange = Range<usize>;
+
+/// Packs tokens as by [`bit_pack_msb`] and returns the range of bits that each
+/// token is packed to, so that a bit offset in the packed program can be
+/// mapped back to its token.
+#[must_use]
+pub fn bit_pack_with_map(toks: &[Token]) -> (Vec<u8>, Vec<BitRange>) {
+    let mut ranges = Vec::with_capacity(toks.len());
+    let mut offset = 0;
+    for &tok in toks {
+        let len = packed_len(tok);
+        ranges.push(offset..offset + len);
+        offset += len;
+    }
+    (bit_pack_msb(toks), ranges)
+}
+
+/// Packs tokens as by [`bit_pack_msb`] and formats the bytes as binary
+/// literals, like `0b00010111, 0b10001000`, for writing fixtures.
+#[must_use]
+pub fn to_bit_string(toks: &[Token]) -> String {
+    let bytes = bit_pack_msb(toks);
+    let literals = bytes.iter().map(|b| format!("0b{b:08b}"));
+    literals.collect::<Vec<_>>().join(", ")
+}
+
+/// Parses bytes formatted as by [`to_bit_string`] and unpacks them as by
+/// [`bit_unpack_msb`]. Literals may be separated by commas or whitespace and
+/// the `0b` prefix is optional. Returns `None`, if a literal is invalid.
+#[must_use]
+pub fn from_bit_string(s: &str) -> Option<Vec<Token>> {
+    let bytes = s
+        .split(|ch: char| ch == ',' || ch.is_whitespace())
+        .filter(|lit| !lit.is_empty())
+        .map(|lit| u8::from_str_radix(lit.strip_prefix("0b").unwrap_or(lit), 2).ok())
+        .collect::<Option<Vec<_>>>()?;
+    Some(bit_unpack_msb(&bytes))
+}
+
+/// Unpacks tokens from a compact bitwise encoding, padded with trailing bits,
+/// and verifies that they form a complete sequence of instructions.
+///
+/// Untrusted input may be corrupt or truncated, so that it unpacks to tokens
+/// that do not parse, such as a trailing partial instruction. The first such
+/// instruction is reported with the byte offset at which it starts.
+///
+/// See [`bit_pack`] for a description of the encoding.
+pub fn bit_unpack_validated<T: BitStore, O: BitOrder>(
+    bits: &[T],
+) -> Result<Vec<Token>, BitUnpackError> {
+    let toks = bit_unpack_padded::<T, O>(bits);
+    let consumed = Cell::new(0usize);
+    let lex = toks.iter().map(|&tok| {
+        consumed.set(consumed.get() + 1);
+        Ok(tok)
+    });
+    let mut parser = Parser::new(lex);
+    loop {
+        let start = consumed.get();
+        match parser.next() {
+            Some(Inst::Error(InstError::ParseError(err))) => {
+                let bit_offset: usize = toks[..start].iter().map(|&tok| packed_len(tok)).sum();
+                return Err(BitUnpackError { offset: bit_offset / 8, err });
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    Ok(toks)
+}
+
+/// An error from unpacking tokens that do not form a complete sequence of
+/// instructions.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BitUnpackError {
+    /// Byte offset of the start of the instruction that failed to parse.
+    pub offset: usize,
+    /// The error from parsing the instruction.
+    pub err: ParseError,
+}
+
+impl fmt::Display for BitUnpackError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "at byte {}: {}", self.offset, self.err)
+    }
+}
+
+impl Error for BitUnpackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.err)
+    }
+}
+
+/// The header that identifies a bit-packed container, as written by
+/// [`bit_pack_container`]. It starts with a NUL byte, so that it is not
+/// mistaken for the start of source text.
+pub const CONTAINER_MAGIC: &[u8; 4] = b"\0wsb";
+
+/// Packs tokens into a container, which is [`CONTAINER_MAGIC`] followed by the
+/// tokens packed as by [`bit_pack_msb`], so that it can be distinguished from
+/// source when read back.
+#[must_use]
+pub fn bit_pack_container(toks: &[Token]) -> Vec<u8> {
+    let mut container = CONTAINER_MAGIC.to_vec();
+    container.extend_from_slice(&bit_pack_msb(toks));
+    container
+}
+
+/// Unpacks and validates tokens from a container written by
+/// [`bit_pack_container`], or returns `None`, if the bytes do not start with
+/// [`CONTAINER_MAGIC`]. Error offsets are from the start of the container.
+#[must_use]
+pub fn bit_unpack_container(bytes: &[u8]) -> Option<Result<Vec<Token>, BitUnpackError>> {
+    let bits = bytes.strip_prefix(CONTAINER_MAGIC)?;
+    Some(
+        bit_unpack_validated::<u8, Msb0>(bits).map_err(|err| BitUnpackError {
+            offset: err.offset + CONTAINER_MAGIC.len(),
+            ..err
+        }),
+    )
+}
+
+/// Verifies that bytes packed as by [`bit_pack_msb`] parse to the expected
+/// instructions, for golden fixtures and archival. Reports the first
+/// instruction that differs.
+pub fn verify_packed(bytes: &[u8], expected: &[RawInst]) -> Result<(), VerifyError> {
+    let toks = bit_unpack_validated::<u8, Msb0>(bytes).map_err(VerifyError::Unpack)?;
+    let mut actual = Parser::new(toks.into_iter().map(Ok));
+    let mut expected = expected.iter();
+    for index in 0.. {
+        match (actual.next(), expected.next()) {
+            (None, None) => return Ok(()),
+            (Some(a), Some(e)) if a == *e => {}
+            (actual, expected) => {
+                return Err(VerifyError::Mismatch {
+                    index,
+                    actual,
+                    expected: expected.cloned(),
+                });
+            }
+        }
+    }
+    unreachable!()
+}
+
+/// An error from [`verify_packed`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum VerifyError {
+    /// The bytes do not unpack to complete instructions.
+    Unpack(BitUnpackError),
+    /// The instruction at `index` differs from the expected instruction, or
+    /// one of the programs ends there, in which case it is `None`.
+    Mismatch {
+        index: usize,
+        actual: Option<RawInst>,
+        expected: Option<RawInst>,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let inst = |inst: &Option<RawInst>| match inst {
+            Some(inst) => format!("`{}`", InstText(inst, NumberEncoding::SignMagnitude)),
+            None => "the end of the program".to_owned(),
+        };
+        match self {
+            VerifyError::Unpack(err) => write!(f, "unpack error: {err}"),
+            VerifyError::Mismatch { index, actual, expected } => write!(
+                f,
+                "at instruction {index}: decoded {}, but expected {}",
+                inst(actual),
+                inst(expected),
+            ),
+        }
+    }
+}
+
+impl Error for VerifyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VerifyError::Unpack(err) => Some(err),
+            VerifyError::Mismatch { .. } => None,
+        }
+    }
+}
+
+/// The number of bits a token is packed to.
+#[inline]
+const fn packed_len(tok: Token) -> usize {
+    match tok {
+        Token::S => 1,
+        Token::T | Token::L => 2,
     }
-    bit_unpack(bits)
 }
 
 /// Specifies the bit order, as a dynamic alternative to
@@ -147,8 +444,8 @@ pub enum BitOrderDynamic {
 #[must_use]
 pub fn bit_pack_dynamic(toks: &[Token], order: BitOrderDynamic) -> Vec<u8> {
     match order {
-        BitOrderDynamic::Lsb0 => bit_pack_padded::<u8, Lsb0>(toks),
-        BitOrderDynamic::Msb0 => bit_pack_padded::<u8, Msb0>(toks),
+        BitOrderDynamic::Lsb0 => bit_pack_lsb(toks),
+        BitOrderDynamic::Msb0 => bit_pack_msb(toks),
     }
 }
 
@@ -159,7 +456,7 @@ pub fn bit_pack_dynamic(toks: &[Token], order: BitOrderDynamic) -> Vec<u8> {
 #[must_use]
 pub fn bit_unpack_dynamic(bits: &[u8], order: BitOrderDynamic) -> Vec<Token> {
     match order {
-        BitOrderDynamic::Lsb0 => bit_unpack_padded::<u8, Lsb0>(bits),
-        BitOrderDynamic::Msb0 => bit_unpack_padded::<u8, Msb0>(bits),
+        BitOrderDynamic::Lsb0 => bit_unpack_lsb(bits),
+        BitOrderDynamic::Msb0 => bit_unpack_msb(bits),
     }
 }
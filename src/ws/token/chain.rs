@@ -0,0 +1,108 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::fmt::{self, Debug, Formatter};
+use std::iter::FusedIterator;
+use std::vec;
+
+use crate::text::EncodingError;
+use crate::ws::token::{Lexer, Token};
+
+/// Concatenates lexers, such as for a program assembled from several source
+/// fragments, yielding the tokens of each in order.
+///
+/// It is lazy, so each lexer is only pulled from once the previous is
+/// exhausted. When a lexer yields an error, [`ChainLexer::source_index`] is the
+/// index of the lexer that failed.
+#[inline]
+#[must_use]
+pub fn chain<'a>(lexers: Vec<Box<dyn Lexer + 'a>>) -> ChainLexer<'a> {
+    let mut lexers = lexers.into_iter();
+    let current = lexers.next();
+    ChainLexer { lexers, current, index: 0 }
+}
+
+/// A lexer that concatenates lexers, as built by [`chain`].
+pub struct ChainLexer<'a> {
+    lexers: vec::IntoIter<Box<dyn Lexer + 'a>>,
+    current: Option<Box<dyn Lexer + 'a>>,
+    index: usize,
+}
+
+impl ChainLexer<'_> {
+    /// The index of the lexer that yielded the last token or error, or, once
+    /// all are exhausted, the number of lexers.
+    #[inline]
+    #[must_use]
+    pub fn source_index(&self) -> usize {
+        self.index
+    }
+}
+
+impl Iterator for ChainLexer<'_> {
+    type Item = Result<Token, EncodingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(lex) = &mut self.current {
+            if let Some(tok) = lex.next() {
+                return Some(tok);
+            }
+            self.current = self.lexers.next();
+            self.index += 1;
+        }
+        None
+    }
+}
+
+impl FusedIterator for ChainLexer<'_> {}
+
+impl Debug for ChainLexer<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChainLexer")
+            .field("index", &self.index)
+            .field("remaining", &self.lexers.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::ws::token::{Mapping, MappingLexer, Token::*};
+
+    #[test]
+    fn chain_sources() {
+        let pulled = Cell::new(false);
+        let lazy = std::iter::from_fn(|| {
+            pulled.set(true);
+            None
+        });
+        let mut lex = chain(vec![
+            Box::new(MappingLexer::new_bytes(b"S T", Mapping::<u8>::STL)),
+            Box::new(MappingLexer::new_bytes(b"", Mapping::<u8>::STL)),
+            Box::new(MappingLexer::new_utf8(b"L\xff", Mapping::<char>::STL, true)),
+            Box::new(lazy),
+        ]);
+        assert_eq!(Some(Ok(S)), lex.next());
+        assert_eq!(Some(Ok(T)), lex.next());
+        assert_eq!((0, false), (lex.source_index(), pulled.get()));
+        assert_eq!(Some(Ok(L)), lex.next());
+        assert_eq!(2, lex.source_index());
+        assert!(matches!(
+            lex.next(),
+            Some(Err(EncodingError::InvalidUtf8(_)))
+        ));
+        assert_eq!((2, false), (lex.source_index(), pulled.get()));
+        assert_eq!(None, lex.next());
+        assert_eq!((4, true), (lex.source_index(), pulled.get()));
+        assert_eq!(None, lex.next());
+        assert_eq!(None, chain(Vec::new()).next());
+    }
+}
@@ -76,13 +76,49 @@ impl Default for Mapping<u8> {
 pub struct MappingLexer<I, T> {
     iter: I,
     map: Mapping<T>,
+    cr: Option<T>,
+    peeked: Option<Result<T, EncodingError>>,
 }
 
 impl<I, T> MappingLexer<I, T> {
     #[inline]
     #[must_use]
     pub const fn new(iter: I, map: Mapping<T>) -> Self {
-        MappingLexer { iter, map }
+        MappingLexer {
+            iter,
+            map,
+            cr: None,
+            peeked: None,
+        }
+    }
+
+    /// Lexes `cr` as `L` and folds `cr` followed by the `L` value into a
+    /// single `L`, so that `\r\n` and `\r` line endings lex like `\n`.
+    #[inline]
+    #[must_use]
+    pub fn with_cr(mut self, cr: T) -> Self {
+        self.cr = Some(cr);
+        self
+    }
+}
+
+impl<I> MappingLexer<I, char> {
+    /// Lexes `\r\n` and `\r` as `L`, for programs with Windows or classic
+    /// Mac OS line endings.
+    #[inline]
+    #[must_use]
+    pub fn accept_crlf(self) -> Self {
+        self.with_cr('\r')
+    }
+}
+
+impl<I> MappingLexer<I, u8> {
+    /// Lexes `\r\n` and `\r` as `L`, for programs with Windows or classic
+    /// Mac OS line endings.
+    #[inline]
+    #[must_use]
+    pub fn accept_crlf(self) -> Self {
+        self.with_cr(b'\r')
     }
 }
 
@@ -98,6 +134,8 @@ impl<'a> MappingLexer<Utf8Iterator<'a>, char> {
 }
 
 impl<'a> MappingLexer<ByteIterator<'a>, u8> {
+    /// Constructs a lexer, that maps raw bytes without decoding UTF-8, so it
+    /// never errors and is the fastest path for ASCII sources.
     #[inline]
     #[must_use]
     pub fn new_bytes<B>(src: &'a B, map: Mapping<u8>) -> Self
@@ -117,8 +155,15 @@ where
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            match self.iter.next() {
+            match self.peeked.take().or_else(|| self.iter.next()) {
                 Some(Ok(v)) => {
+                    if self.cr.as_ref() == Some(&v) {
+                        match self.iter.next() {
+                            Some(Ok(after)) if after == self.map.l => {}
+                            after => self.peeked = after,
+                        }
+                        return Some(Ok(Token::L));
+                    }
                     if let Some(tok) = self.map.map(&v) {
                         return Some(Ok(tok));
                     }
@@ -219,6 +264,50 @@ impl Iterator for BytesMappingLexer<'_> {
 
 impl FusedIterator for BytesMappingLexer<'_> {}
 
+/// A lexer for source in which tokens are spelled as strings, such as words
+/// like `space`, `tab`, and `lf` in pedagogical dialects.
+///
+/// Unlike [`BytesMappingLexer`], the lexemes may be prefixes of each other,
+/// since the longest lexeme that matches is chosen. Empty lexemes never match.
+/// Text that does not start a lexeme is skipped one `char` at a time.
+#[derive(Clone, Debug)]
+pub struct StrMappingLexer<'a> {
+    src: &'a str,
+    offset: usize,
+    map: Mapping<&'a str>,
+}
+
+impl<'a> StrMappingLexer<'a> {
+    #[inline]
+    #[must_use]
+    pub fn new(src: &'a str, map: Mapping<&'a str>) -> Self {
+        StrMappingLexer { src, offset: 0, map }
+    }
+}
+
+impl Iterator for StrMappingLexer<'_> {
+    type Item = Result<Token, EncodingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(ch) = self.src[self.offset..].chars().next() {
+            let rest = &self.src[self.offset..];
+            let longest = Token::all()
+                .into_iter()
+                .map(|tok| (tok, *self.map.map_token(tok)))
+                .filter(|(_, lexeme)| !lexeme.is_empty() && rest.starts_with(lexeme))
+                .max_by_key(|(_, lexeme)| lexeme.len());
+            if let Some((tok, lexeme)) = longest {
+                self.offset += lexeme.len();
+                return Some(Ok(tok));
+            }
+            self.offset += ch.len_utf8();
+        }
+        None
+    }
+}
+
+impl FusedIterator for StrMappingLexer<'_> {}
+
 #[must_use]
 pub fn lex_mapping<'a>(
     src: &'a [u8],
@@ -254,3 +343,17 @@ pub fn lex_mapping<'a>(
     let map = BytesMapping::new(s, t, l)?;
     Some(Box::new(BytesMappingLexer::new(src, map)))
 }
+
+/// Counts the occurrences of each token in the source, indexed by the token
+/// discriminant. Bytes that are not mapped to a token are ignored.
+///
+/// This is a cheap check of whether a file plausibly contains a Whitespace
+/// program, before fully parsing it.
+#[must_use]
+pub fn token_histogram(src: &[u8], map: Mapping<u8>) -> [usize; 3] {
+    let mut counts = [0; 3];
+    for tok in MappingLexer::new_bytes(src, map).flatten() {
+        counts[tok as usize] += 1;
+    }
+    counts
+}
@@ -7,13 +7,24 @@
 // Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
 
 pub use bit_pack::*;
+pub use chain::*;
 pub use mapping::*;
+pub use sink::*;
+pub use spanned::*;
+pub use token_array::*;
 pub use token_vec::*;
 
+pub use crate::syntax::TokenSeq;
+
 mod bit_pack;
+mod chain;
 mod mapping;
+mod sink;
+mod spanned;
+mod token_array;
 mod token_vec;
 
+use std::fmt::{self, Display, Formatter};
 use std::mem;
 
 use crate::syntax::VariantIndex;
@@ -21,7 +32,43 @@ use crate::text::EncodingError;
 
 pub trait Lexer = Iterator<Item = Result<Token, EncodingError>>;
 
+/// Builds a [`TokenSeq`] of Whitespace tokens, written as `S`, `T`, and `L`,
+/// which can be used in const contexts, such as for static tables, like
+/// `const PUSH: TokenSeq<Token> = token_seq!(S S);`.
+#[macro_export]
+macro_rules! token_seq(($($tok:ident)*) => {
+    $crate::syntax::TokenSeq::<$crate::ws::token::Token>::from_indices(
+        &[$($crate::ws::token::Token::$tok as u32),*],
+    )
+});
+
+/// Provided methods for lexers, since [`Lexer`] is a trait alias and cannot
+/// have methods itself.
+pub trait LexerExt: Lexer + Sized {
+    /// Collects all tokens, stopping at the first error.
+    #[inline]
+    fn collect_tokens(self) -> Result<Vec<Token>, EncodingError> {
+        self.collect()
+    }
+
+    /// Counts all tokens, stopping at the first error.
+    #[inline]
+    fn count_tokens(self) -> Result<usize, EncodingError> {
+        let mut count = 0;
+        for tok in self {
+            tok?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl<L: Lexer> LexerExt for L {}
+
 /// Lexical tokens for Whitespace.
+///
+/// The discriminants are `S` = 0, `T` = 1, and `L` = 2, which are stable, so
+/// they can be serialized with [`Token::to_u8`] and [`Token::from_u8`].
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Token {
@@ -33,6 +80,67 @@ pub enum Token {
     L,
 }
 
+impl Token {
+    /// Every token, in order of their variant index.
+    #[inline]
+    #[must_use]
+    pub const fn all() -> [Token; 3] {
+        [Token::S, Token::T, Token::L]
+    }
+
+    /// Converts the token to its discriminant.
+    #[inline]
+    #[must_use]
+    pub const fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// Converts a discriminant to its token, or `None`, if it is out of range.
+    #[inline]
+    #[must_use]
+    pub const fn from_u8(n: u8) -> Option<Self> {
+        match n {
+            0 => Some(Token::S),
+            1 => Some(Token::T),
+            2 => Some(Token::L),
+            _ => None,
+        }
+    }
+
+    /// Converts a token in an argument to its bit value: `S` is `0` and `T` is
+    /// `1`. `L` terminates arguments, so has no bit value.
+    #[inline]
+    #[must_use]
+    pub const fn as_bit(self) -> Option<bool> {
+        match self {
+            Token::S => Some(false),
+            Token::T => Some(true),
+            Token::L => None,
+        }
+    }
+
+    /// Converts a bit in an argument to its token: `0` is `S` and `1` is `T`.
+    #[inline]
+    #[must_use]
+    pub const fn from_bit(bit: bool) -> Self {
+        if bit {
+            Token::T
+        } else {
+            Token::S
+        }
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Token::S => "S",
+            Token::T => "T",
+            Token::L => "L",
+        })
+    }
+}
+
 impl VariantIndex for Token {
     const COUNT: u32 = 3;
     #[inline]
@@ -0,0 +1,121 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use bitvec::prelude::*;
+
+use crate::ws::token::Token;
+
+/// A destination for tokens, such as a buffer or an encoder.
+pub trait TokenSink {
+    /// Appends a token.
+    fn push(&mut self, tok: Token);
+}
+
+impl TokenSink for Vec<Token> {
+    #[inline]
+    fn push(&mut self, tok: Token) {
+        Vec::push(self, tok);
+    }
+}
+
+/// A sink that bit packs tokens as they are pushed.
+///
+/// See [`bit_pack`](crate::ws::token::bit_pack) for a description of the
+/// encoding.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BitPacker<T: BitStore = u8, O: BitOrder = Msb0> {
+    bits: BitVec<T, O>,
+}
+
+impl<T: BitStore, O: BitOrder> BitPacker<T, O> {
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        BitPacker { bits: BitVec::new() }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn with_capacity(toks: usize) -> Self {
+        BitPacker {
+            bits: BitVec::with_capacity(toks * 2),
+        }
+    }
+
+    /// The packed bits, where the final element in the store may be only
+    /// partially filled.
+    #[inline]
+    #[must_use]
+    pub fn into_bits(self) -> BitVec<T, O> {
+        self.bits
+    }
+
+    /// The packed bits, padded with trailing bits, as by
+    /// [`bit_pack_padded`](crate::ws::token::bit_pack_padded).
+    #[inline]
+    #[must_use]
+    pub fn into_padded(mut self) -> Vec<T> {
+        // Follow a final `0` bit with a marker `1` bit to avoid ambiguity.
+        if self.bits.last().as_deref() == Some(&false) {
+            self.bits.push(true);
+        }
+        self.bits.set_uninitialized(false);
+        self.bits.into_vec()
+    }
+}
+
+impl<T: BitStore, O: BitOrder> TokenSink for BitPacker<T, O> {
+    #[inline]
+    fn push(&mut self, tok: Token) {
+        match tok {
+            Token::S => self.bits.push(false),
+            Token::T => {
+                self.bits.push(true);
+                self.bits.push(false);
+            }
+            Token::L => {
+                self.bits.push(true);
+                self.bits.push(true);
+            }
+        }
+    }
+}
+
+/// A sink that only counts tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CountingSink {
+    len: usize,
+}
+
+impl CountingSink {
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        CountingSink { len: 0 }
+    }
+
+    /// The number of tokens pushed.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl TokenSink for CountingSink {
+    #[inline]
+    fn push(&mut self, _tok: Token) {
+        self.len += 1;
+    }
+}
@@ -0,0 +1,256 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+//! Lexing with source locations, for tooling such as editors.
+
+use std::iter::FusedIterator;
+use std::ops::Range;
+
+use bstr::decode_utf8;
+
+use crate::ws::token::{Mapping, Token};
+
+/// A token with the byte range of its source character.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SpannedToken {
+    pub tok: Token,
+    pub span: Range<usize>,
+}
+
+/// A token or a run of non-token characters, as yielded by [`TriviaLexer`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Lexeme {
+    Token(SpannedToken),
+    /// The byte range of a run of characters that are not tokens, such as
+    /// comments.
+    Trivia(Range<usize>),
+}
+
+/// A lexer for UTF-8 source, that yields tokens with their byte ranges.
+///
+/// Unlike [`MappingLexer`](crate::ws::token::MappingLexer), invalid UTF-8
+/// sequences are not errors and are skipped like any other non-token
+/// character, since source being edited is frequently malformed.
+#[derive(Clone, Debug)]
+pub struct SpannedLexer<'a> {
+    src: &'a [u8],
+    offset: usize,
+    end: usize,
+    map: &'a Mapping<char>,
+}
+
+impl<'a> SpannedLexer<'a> {
+    #[inline]
+    #[must_use]
+    pub fn new<B>(src: &'a B, map: &'a Mapping<char>) -> Self
+    where
+        B: AsRef<[u8]> + ?Sized,
+    {
+        let src = src.as_ref();
+        SpannedLexer {
+            src,
+            offset: 0,
+            end: src.len(),
+            map,
+        }
+    }
+
+    /// Constructs a lexer for the byte range `range` of `src`, which must start
+    /// and end at character boundaries.
+    #[inline]
+    #[must_use]
+    pub fn with_range<B>(src: &'a B, range: Range<usize>, map: &'a Mapping<char>) -> Self
+    where
+        B: AsRef<[u8]> + ?Sized,
+    {
+        let src = src.as_ref();
+        debug_assert!(range.start <= range.end && range.end <= src.len());
+        SpannedLexer {
+            src,
+            offset: range.start,
+            end: range.end,
+            map,
+        }
+    }
+}
+
+impl Iterator for SpannedLexer<'_> {
+    type Item = SpannedToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.offset < self.end {
+            let start = self.offset;
+            let (ch, size) = decode_utf8(&self.src[start..self.end]);
+            self.offset += size;
+            if let Some(tok) = ch.and_then(|ch| self.map.map(&ch)) {
+                return Some(SpannedToken { tok, span: start..self.offset });
+            }
+        }
+        None
+    }
+}
+
+impl FusedIterator for SpannedLexer<'_> {}
+
+impl<'a> SpannedLexer<'a> {
+    /// Converts this lexer into one that also yields the non-token characters
+    /// between tokens as trivia, so that the source can be reproduced exactly.
+    #[inline]
+    #[must_use]
+    pub fn with_trivia(self) -> TriviaLexer<'a> {
+        TriviaLexer { lex: self, pending: None }
+    }
+}
+
+/// A lexer, that yields both tokens and the trivia between them, from
+/// [`SpannedLexer::with_trivia`].
+///
+/// The spans of the lexemes are contiguous and cover the whole source range.
+#[derive(Clone, Debug)]
+pub struct TriviaLexer<'a> {
+    lex: SpannedLexer<'a>,
+    pending: Option<SpannedToken>,
+}
+
+impl Iterator for TriviaLexer<'_> {
+    type Item = Lexeme;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(tok) = self.pending.take() {
+            return Some(Lexeme::Token(tok));
+        }
+        let start = self.lex.offset;
+        match self.lex.next() {
+            Some(tok) if tok.span.start == start => Some(Lexeme::Token(tok)),
+            Some(tok) => {
+                let trivia = start..tok.span.start;
+                self.pending = Some(tok);
+                Some(Lexeme::Trivia(trivia))
+            }
+            None if start < self.lex.offset => Some(Lexeme::Trivia(start..self.lex.offset)),
+            None => None,
+        }
+    }
+}
+
+impl FusedIterator for TriviaLexer<'_> {}
+
+/// A lexer that keeps the tokens of a source buffer up to date as it is edited,
+/// by re-lexing only the edited region.
+///
+/// Since each token is a single character, an edit only affects the tokens
+/// that it overlaps, so the tokens between the nearest unchanged tokens on
+/// either side are re-lexed and the rest are kept or shifted.
+#[derive(Clone, Debug)]
+pub struct IncrementalLexer {
+    map: Mapping<char>,
+    toks: Vec<SpannedToken>,
+}
+
+impl IncrementalLexer {
+    /// Lexes all of `src`.
+    #[must_use]
+    pub fn new<B: AsRef<[u8]> + ?Sized>(src: &B, map: Mapping<char>) -> Self {
+        let toks = SpannedLexer::new(src, &map).collect();
+        IncrementalLexer { map, toks }
+    }
+
+    /// The tokens of the source, as of the most recent edit.
+    #[inline]
+    #[must_use]
+    pub fn tokens(&self) -> &[SpannedToken] {
+        &self.toks
+    }
+
+    /// Updates the tokens for an edit, which replaced the bytes in `old_range`
+    /// of the previous source with `new_len` bytes, producing `src`, and
+    /// returns the updated tokens.
+    pub fn edit<B: AsRef<[u8]> + ?Sized>(
+        &mut self,
+        src: &B,
+        old_range: Range<usize>,
+        new_len: usize,
+    ) -> &[SpannedToken] {
+        let src = src.as_ref();
+        // Tokens wholly before or after the edit are unchanged.
+        let before = self.toks.partition_point(|t| t.span.end <= old_range.start);
+        let after = before + self.toks[before..].partition_point(|t| t.span.start < old_range.end);
+
+        let relex_start = if before == 0 {
+            0
+        } else {
+            self.toks[before - 1].span.end
+        };
+        let relex_end = match self.toks.get(after) {
+            Some(t) => t.span.start - old_range.len() + new_len,
+            None => src.len(),
+        };
+
+        for t in &mut self.toks[after..] {
+            t.span.start = t.span.start - old_range.len() + new_len;
+            t.span.end = t.span.end - old_range.len() + new_len;
+        }
+        let relexed = SpannedLexer::with_range(src, relex_start..relex_end, &self.map);
+        self.toks.splice(before..after, relexed.collect::<Vec<_>>());
+        &self.toks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit() {
+        let mut src = "S T garbage\nL ST".to_owned();
+        let mut lex = IncrementalLexer::new(&src, Mapping::<char>::STL);
+        for (range, replacement) in [
+            (1..1, "T"),
+            (2..3, ""),
+            (5..8, "L\u{3bb}S"),
+            (0..0, "\u{2028}"),
+            (3..17, "T"),
+            (0..7, ""),
+            (0..0, "STL"),
+        ] {
+            src.replace_range(range.clone(), replacement);
+            let toks = lex.edit(&src, range, replacement.len());
+            let expected = SpannedLexer::new(&src, &Mapping::<char>::STL).collect::<Vec<_>>();
+            assert_eq!(expected, toks, "incremental lex of {src:?}");
+        }
+        assert_eq!(3, lex.tokens().len());
+    }
+
+    #[test]
+    fn trivia() {
+        let src = "S# push\u{3bb}\nT\u{e9}L\n";
+        let lexemes = SpannedLexer::new(src, &Mapping::<char>::STL)
+            .with_trivia()
+            .collect::<Vec<_>>();
+        let tok = |tok, span| Lexeme::Token(SpannedToken { tok, span });
+        let expected = vec![
+            tok(Token::S, 0..1),
+            Lexeme::Trivia(1..10),
+            tok(Token::T, 10..11),
+            Lexeme::Trivia(11..13),
+            tok(Token::L, 13..14),
+            Lexeme::Trivia(14..15),
+        ];
+        assert_eq!(expected, lexemes);
+
+        // Rewrite the tokens, while keeping the comments.
+        let mut out = String::new();
+        for lexeme in lexemes {
+            match lexeme {
+                Lexeme::Token(t) => out.push_str(&t.tok.to_string().to_lowercase()),
+                Lexeme::Trivia(span) => out.push_str(&src[span]),
+            }
+        }
+        assert_eq!("s# push\u{3bb}\nt\u{e9}l\n", out);
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::ops::Deref;
+
+use arrayvec::ArrayVec;
+
+use crate::ws::token::{Token, TokenSink};
+
+/// A growable sequence of tokens.
+///
+/// Pushing to a buffer that is at capacity panics.
+pub trait TokenBuffer: TokenSink + Deref<Target = [Token]> {
+    /// Appends a slice of tokens.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer does not have the capacity for the tokens.
+    fn append(&mut self, toks: &[Token]) {
+        for &tok in toks {
+            self.push(tok);
+        }
+    }
+}
+
+impl TokenBuffer for Vec<Token> {
+    #[inline]
+    fn append(&mut self, toks: &[Token]) {
+        self.extend_from_slice(toks);
+    }
+}
+
+/// A sequence of at most `N` tokens, that is stored inline without
+/// allocating.
+///
+/// The alternate debug format, `{:#?}`, prints the tokens compactly as a
+/// string of `S`, `T`, and `L`, which is truncated for long arrays.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TokenArray<const N: usize> {
+    toks: ArrayVec<Token, N>,
+}
+
+impl<const N: usize> TokenArray<N> {
+    pub const CAPACITY: usize = N;
+
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        TokenArray { toks: ArrayVec::new_const() }
+    }
+
+    /// Appends a token, or returns it, if the array is at capacity.
+    #[inline]
+    pub fn try_push(&mut self, tok: Token) -> Result<(), Token> {
+        self.toks
+            .try_push(tok)
+            .map_err(arrayvec::CapacityError::element)
+    }
+
+    /// Appends the tokens of an iterator, until the array is at capacity, and
+    /// returns the first token that does not fit, if any.
+    pub fn try_extend<I: IntoIterator<Item = Token>>(&mut self, iter: I) -> Result<(), Token> {
+        for tok in iter {
+            self.try_push(tok)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.toks.is_full()
+    }
+}
+
+impl<const N: usize> TokenSink for TokenArray<N> {
+    #[inline]
+    fn push(&mut self, tok: Token) {
+        self.toks.push(tok);
+    }
+}
+
+impl<const N: usize> TokenBuffer for TokenArray<N> {
+    #[inline]
+    fn append(&mut self, toks: &[Token]) {
+        self.toks.try_extend_from_slice(toks).unwrap();
+    }
+}
+
+impl<const N: usize> Extend<Token> for TokenArray<N> {
+    /// Appends the tokens of an iterator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the array does not have the capacity for the tokens, like
+    /// [`TokenSink::push`].
+    #[inline]
+    fn extend<I: IntoIterator<Item = Token>>(&mut self, iter: I) {
+        for tok in iter {
+            self.push(tok);
+        }
+    }
+}
+
+impl<'a, const N: usize> Extend<&'a Token> for TokenArray<N> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = &'a Token>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+impl<const N: usize> FromIterator<Token> for TokenArray<N> {
+    /// Collects tokens into an array.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the iterator yields more than `N` tokens.
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = Token>>(iter: I) -> Self {
+        let mut arr = TokenArray::new();
+        arr.extend(iter);
+        arr
+    }
+}
+
+impl<const N: usize> Deref for TokenArray<N> {
+    type Target = [Token];
+
+    #[inline]
+    fn deref(&self) -> &[Token] {
+        &self.toks
+    }
+}
+
+impl<const N: usize> Debug for TokenArray<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        /// The number of tokens printed by the alternate format, before
+        /// truncating.
+        const MAX_COMPACT: usize = 64;
+
+        if !f.alternate() {
+            return f
+                .debug_struct("TokenArray")
+                .field("toks", &self.toks)
+                .finish();
+        }
+        f.write_str("TokenArray(\"")?;
+        for tok in self.toks.iter().take(MAX_COMPACT) {
+            Display::fmt(tok, f)?;
+        }
+        if self.len() > MAX_COMPACT {
+            write!(f, "…\", {} tokens)", self.len())
+        } else {
+            f.write_str("\")")
+        }
+    }
+}
+
+impl<const N: usize> TryFrom<&[Token]> for TokenArray<N> {
+    type Error = arrayvec::CapacityError;
+
+    #[inline]
+    fn try_from(toks: &[Token]) -> Result<Self, Self::Error> {
+        Ok(TokenArray { toks: ArrayVec::try_from(toks)? })
+    }
+}
+
+impl<'a, const N: usize> IntoIterator for &'a TokenArray<N> {
+    type Item = &'a Token;
+    type IntoIter = std::slice::Iter<'a, Token>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.toks.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ws::token::Token::*;
+
+    fn fill<B: TokenBuffer>(buf: &mut B) {
+        buf.push(S);
+        buf.append(&[T; 63]);
+    }
+
+    #[test]
+    fn shared_surface() {
+        let mut arr = TokenArray::<64>::new();
+        let mut vec = Vec::new();
+        fill(&mut arr);
+        fill(&mut vec);
+        assert_eq!(&vec[..], &arr[..]);
+        assert_eq!(Some(&T), arr.get(63));
+        assert_eq!(64, arr.iter().count());
+        assert!(arr.is_full());
+        assert_eq!(Err(L), arr.try_push(L));
+        assert!(TokenArray::<2>::try_from(&[S, T, L][..]).is_err());
+    }
+
+    #[test]
+    fn extend() {
+        let mut arr = TokenArray::<4>::new();
+        arr.extend([S, T]);
+        arr.extend(&[L, S]);
+        assert!(arr.is_full());
+        assert_eq!([S, T, L, S], &arr[..]);
+        assert_eq!(Ok(()), arr.try_extend([]));
+        assert_eq!(Err(T), arr.try_extend([T, L]));
+
+        let mut arr = TokenArray::<4>::new();
+        assert_eq!(Err(L), arr.try_extend([S, T, S, T, L]));
+        assert_eq!([S, T, S, T], &arr[..]);
+        assert_eq!(arr, [S, T, S, T].into_iter().collect());
+    }
+
+    #[test]
+    #[should_panic(expected = "insufficient capacity")]
+    fn extend_past_capacity() {
+        let mut arr = TokenArray::<2>::new();
+        arr.extend([S, T, L]);
+    }
+
+    #[test]
+    fn debug() {
+        let arr = TokenArray::<100>::try_from(&[S, T, L][..]).unwrap();
+        assert_eq!("TokenArray { toks: [S, T, L] }", format!("{arr:?}"));
+        assert_eq!("TokenArray(\"STL\")", format!("{arr:#?}"));
+        let mut arr = TokenArray::<100>::new();
+        arr.append(&[T; 70]);
+        let expected = format!("TokenArray(\"{}…\", 70 tokens)", "T".repeat(64));
+        assert_eq!(expected, format!("{arr:#?}"));
+    }
+}
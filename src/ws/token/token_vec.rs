@@ -9,12 +9,26 @@
 use bitvec::prelude::*;
 
 use crate::syntax::TokenSeq;
-use crate::ws::token::Token;
+use crate::ws::token::{bit_pack_padded, bit_unpack_padded, Token};
 
+/// A growable sequence of tokens, that can be extended with the bits of an
+/// argument.
+///
+/// For short sequences that are compared or hashed, like opcodes, see the
+/// packed [`TokenSeq`].
 pub trait TokenVec {
     #[must_use]
     fn from_bits<T: BitStore, O: BitOrder>(bits: &BitSlice<T, O>) -> Self;
     fn append_bits<T: BitStore, O: BitOrder>(&mut self, bits: &BitSlice<T, O>);
+
+    /// Packs the tokens into a compact bitwise encoding, padded with trailing
+    /// bits, as by [`bit_pack_padded`].
+    #[must_use]
+    fn bit_pack<T: BitStore, O: BitOrder>(&self) -> Vec<T>;
+    /// Unpacks tokens from a compact bitwise encoding, padded with trailing
+    /// bits, as by [`bit_unpack_padded`].
+    #[must_use]
+    fn bit_unpack<T: BitStore, O: BitOrder>(bits: &[T]) -> Self;
 }
 
 impl TokenVec for Vec<Token> {
@@ -27,10 +41,17 @@ impl TokenVec for Vec<Token> {
 
     #[inline]
     fn append_bits<T: BitStore, O: BitOrder>(&mut self, bits: &BitSlice<T, O>) {
-        self.reserve(bits.len());
-        for bit in bits {
-            self.push(if *bit { Token::T } else { Token::S });
-        }
+        self.extend(bits.iter().by_vals().map(Token::from_bit));
+    }
+
+    #[inline]
+    fn bit_pack<T: BitStore, O: BitOrder>(&self) -> Vec<T> {
+        bit_pack_padded::<T, O>(self)
+    }
+
+    #[inline]
+    fn bit_unpack<T: BitStore, O: BitOrder>(bits: &[T]) -> Self {
+        bit_unpack_padded::<T, O>(bits)
     }
 }
 
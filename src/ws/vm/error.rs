@@ -0,0 +1,134 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+use bitvec::vec::BitVec;
+use rug::Integer;
+
+use crate::text::EncodingError;
+use crate::ws::inst::{InstError, Opcode};
+
+/// A trap while executing a program.
+///
+/// Traps from executing a program are wrapped in [`RuntimeError::At`], with
+/// the index of the instruction.
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// A trap at the instruction with index `pc`.
+    At { pc: usize, err: Box<RuntimeError> },
+    /// An instruction needed `needed` values, but the stack had only `depth`.
+    StackUnderflow { depth: usize, needed: usize },
+    /// The index of `copy` or `slide` was negative, or was beyond the stack
+    /// with [`StackIndexPolicy::Strict`](crate::ws::vm::StackIndexPolicy::Strict).
+    StackIndexOutOfRange,
+    /// `div` or `mod` had a divisor of zero.
+    DivisionByZero,
+    /// A `call` or jump referenced a label that is not defined.
+    UndefinedLabel(BitVec),
+    /// `ret` executed with no active call.
+    RetWithoutCall,
+    /// `call` would exceed the maximum call depth.
+    CallStackOverflow,
+    /// A store to a new address would exceed the maximum number of heap
+    /// cells.
+    HeapLimitExceeded,
+    /// Execution continued past the last instruction, without an `end`.
+    NoEnd,
+    /// An instruction that failed to parse was executed.
+    InvalidInst(InstError),
+    /// `printc` was given a value that is not a Unicode scalar value.
+    InvalidChar(Integer),
+    /// `readc` read invalid UTF-8.
+    InvalidUtf8(EncodingError),
+    /// `readi` read a line that is not an integer.
+    InvalidInt(String),
+    /// `readc` or `readi` found no input remaining, with
+    /// [`EofPolicy::Error`](crate::ws::vm::EofPolicy::Error).
+    InputExhausted,
+    /// The instruction is not supported by this interpreter.
+    Unsupported(Opcode),
+    /// Reading input or writing output failed.
+    Io(io::Error),
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::At { pc, err } => write!(f, "at instruction {pc}: {err}"),
+            RuntimeError::StackUnderflow { depth, needed } => {
+                write!(
+                    f,
+                    "stack underflow: needed {needed} values, but the stack had {depth}"
+                )
+            }
+            RuntimeError::StackIndexOutOfRange => write!(f, "stack index out of range"),
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::UndefinedLabel(label) => write!(f, "undefined label {label}"),
+            RuntimeError::RetWithoutCall => write!(f, "ret without call"),
+            RuntimeError::CallStackOverflow => write!(f, "call stack overflow"),
+            RuntimeError::HeapLimitExceeded => write!(f, "heap limit exceeded"),
+            RuntimeError::NoEnd => write!(f, "execution reached the end without end"),
+            RuntimeError::InvalidInst(_) => write!(f, "executed invalid instruction"),
+            RuntimeError::InvalidChar(n) => write!(f, "invalid char {n}"),
+            RuntimeError::InvalidUtf8(_) => write!(f, "read invalid UTF-8"),
+            RuntimeError::InvalidInt(line) => write!(f, "read invalid integer {line:?}"),
+            RuntimeError::InputExhausted => write!(f, "input exhausted"),
+            RuntimeError::Unsupported(opcode) => write!(f, "unsupported instruction {opcode}"),
+            RuntimeError::Io(_) => write!(f, "I/O error"),
+        }
+    }
+}
+
+impl Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RuntimeError::At { err, .. } => err.source(),
+            RuntimeError::InvalidInst(err) => Some(err),
+            RuntimeError::InvalidUtf8(err) => Some(err),
+            RuntimeError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl RuntimeError {
+    /// The index of the instruction that trapped, if known.
+    #[inline]
+    #[must_use]
+    pub fn pc(&self) -> Option<usize> {
+        match self {
+            RuntimeError::At { pc, .. } => Some(*pc),
+            _ => None,
+        }
+    }
+
+    /// The trap, without the index of the instruction.
+    #[inline]
+    #[must_use]
+    pub fn kind(&self) -> &RuntimeError {
+        match self {
+            RuntimeError::At { err, .. } => err.kind(),
+            err => err,
+        }
+    }
+
+    #[inline]
+    pub(super) fn at(self, pc: usize) -> Self {
+        RuntimeError::At { pc, err: Box::new(self) }
+    }
+}
+
+impl From<io::Error> for RuntimeError {
+    #[inline]
+    fn from(err: io::Error) -> Self {
+        RuntimeError::Io(err)
+    }
+}
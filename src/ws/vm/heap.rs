@@ -0,0 +1,127 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Read, Write};
+
+use rug::integer::Order;
+use rug::Integer;
+
+/// Sparse heap of arbitrary-precision integers, indexed by arbitrary-precision
+/// addresses. Cells that have not been stored to hold zero.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Heap {
+    cells: HashMap<Integer, Integer>,
+}
+
+impl Heap {
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Heap::default()
+    }
+
+    /// Gets the value at an address, or `None`, if it has not been stored to.
+    #[inline]
+    #[must_use]
+    pub fn get(&self, addr: &Integer) -> Option<&Integer> {
+        self.cells.get(addr)
+    }
+
+    /// Stores a value at an address.
+    #[inline]
+    pub fn store(&mut self, addr: Integer, value: Integer) {
+        self.cells.insert(addr, value);
+    }
+
+    /// The number of cells that have been stored to.
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Iterates the cells that have been stored to, in arbitrary order.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&Integer, &Integer)> {
+        self.cells.iter()
+    }
+
+    /// Iterates the cells that have been stored to, in ascending order of
+    /// address, so that the order is reproducible.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&Integer, &Integer)> {
+        let mut cells = self.cells.iter().collect::<Vec<_>>();
+        cells.sort_unstable_by_key(|&(addr, _)| addr);
+        cells.into_iter()
+    }
+
+    /// Writes the stored cells, ordered by address.
+    ///
+    /// The format is a little-endian `u64` count of cells, followed by the
+    /// address and value of each. Each integer is written as a little-endian
+    /// `i64` byte length, which is negative for negative integers, followed by
+    /// the little-endian bytes of its magnitude.
+    pub fn write_to<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&(self.len() as u64).to_le_bytes())?;
+        for (addr, value) in self.iter_sorted() {
+            write_integer(w, addr)?;
+            write_integer(w, value)?;
+        }
+        Ok(())
+    }
+
+    /// Reads cells in the format written by [`Heap::write_to`].
+    pub fn read_from<R: Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+        let mut buf = [0; 8];
+        r.read_exact(&mut buf)?;
+        let len = u64::from_le_bytes(buf);
+        let mut heap = Heap::new();
+        for _ in 0..len {
+            let addr = read_integer(r)?;
+            let value = read_integer(r)?;
+            heap.store(addr, value);
+        }
+        Ok(heap)
+    }
+}
+
+impl Debug for Heap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter_sorted()).finish()
+    }
+}
+
+fn write_integer<W: Write + ?Sized>(w: &mut W, int: &Integer) -> io::Result<()> {
+    let digits = int.to_digits::<u8>(Order::Lsf);
+    let len = i64::try_from(digits.len()).unwrap();
+    let len = if *int < 0 { -len } else { len };
+    w.write_all(&len.to_le_bytes())?;
+    w.write_all(&digits)
+}
+
+fn read_integer<R: Read + ?Sized>(r: &mut R) -> io::Result<Integer> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    let len = i64::from_le_bytes(buf);
+    let size = usize::try_from(len.unsigned_abs())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "integer too large"))?;
+    let mut digits = Vec::new();
+    r.take(size as u64).read_to_end(&mut digits)?;
+    if digits.len() != size {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let int = Integer::from_digits(&digits, Order::Lsf);
+    Ok(if len < 0 { -int } else { int })
+}
@@ -0,0 +1,1481 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+//! Interpreter for Whitespace instructions.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Read, Write};
+use std::mem;
+
+use arrayvec::ArrayVec;
+use bitvec::slice::BitSlice;
+use bitvec::vec::BitVec;
+use bstr::decode_utf8;
+pub use error::*;
+pub use heap::*;
+pub use options::*;
+use rug::ops::{DivRounding, RemRounding};
+use rug::Integer;
+pub use stream::StepOutcome;
+pub use transcript::*;
+pub use watch::{HeapWatch, StackWatch};
+
+use crate::text::EncodingError;
+use crate::ws::inst::{Inst, RawInst};
+use crate::ws::syntax::NumberEncoding;
+use crate::ws::vm::stream::StreamBuffers;
+use crate::ws::vm::trace::Trace;
+use crate::ws::vm::watch::Watches;
+
+mod error;
+mod heap;
+mod options;
+mod stream;
+mod trace;
+mod transcript;
+mod watch;
+
+/// A virtual machine, that executes a Whitespace program.
+///
+/// Labels are resolved when the machine is constructed; when a label is
+/// defined multiple times, the first definition is used, like wspace.
+///
+/// Cloning a machine does not clone its watchpoints or trace writer.
+#[derive(Clone, Debug)]
+pub struct Vm {
+    insts: Vec<RawInst>,
+    labels: HashMap<BitVec, usize>,
+    pc: usize,
+    stack: Vec<Integer>,
+    calls: Vec<usize>,
+    heap: Heap,
+    halted: bool,
+    options: VmOptions,
+    watches: Option<Watches>,
+    breakpoints: HashSet<usize>,
+    /// Whether execution stopped at the breakpoint at `pc`, so that the next
+    /// step executes it.
+    at_breakpoint: bool,
+    stream: StreamBuffers,
+    trace: Trace,
+}
+
+/// The state of a machine after executing an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StepResult {
+    /// Execution can continue.
+    Continue,
+    /// The program executed `end`.
+    Halted,
+    /// Execution stopped before the instruction at a breakpoint, which is
+    /// executed by the next step.
+    Breakpoint,
+}
+
+impl Vm {
+    #[inline]
+    #[must_use]
+    pub fn new(insts: Vec<RawInst>) -> Self {
+        Vm::with_options(insts, VmOptions::default())
+    }
+
+    #[must_use]
+    pub fn with_options(insts: Vec<RawInst>, options: VmOptions) -> Self {
+        let mut labels = HashMap::new();
+        for (pc, inst) in insts.iter().enumerate() {
+            if let Inst::Label(l) = inst {
+                labels.entry(l.clone()).or_insert(pc);
+            }
+        }
+        Vm {
+            insts,
+            labels,
+            pc: 0,
+            stack: Vec::new(),
+            calls: Vec::new(),
+            heap: Heap::new(),
+            halted: false,
+            options,
+            watches: None,
+            breakpoints: HashSet::new(),
+            at_breakpoint: false,
+            stream: StreamBuffers::default(),
+            trace: Trace::default(),
+        }
+    }
+
+    /// Constructs a machine that starts with the given stack, with the top at
+    /// the end, and heap, for testing a fragment of a program in isolation.
+    ///
+    /// Execution starts at the first instruction, unless overridden with
+    /// [`Vm::set_pc`].
+    #[must_use]
+    pub fn with_state(insts: Vec<RawInst>, stack: Vec<Integer>, heap: Heap) -> Self {
+        let mut vm = Vm::new(insts);
+        vm.stack = stack;
+        vm.heap = heap;
+        vm
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn options(&self) -> &VmOptions {
+        &self.options
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn insts(&self) -> &[RawInst] {
+        &self.insts
+    }
+
+    /// The index of the next instruction to execute.
+    #[inline]
+    #[must_use]
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Sets the index of the next instruction to execute.
+    #[inline]
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+        self.at_breakpoint = false;
+    }
+
+    /// The value stack, with the top at the end.
+    #[inline]
+    #[must_use]
+    pub fn stack(&self) -> &[Integer] {
+        &self.stack
+    }
+
+    /// The return addresses of the active calls, with the innermost at the end.
+    #[inline]
+    #[must_use]
+    pub fn call_stack(&self) -> &[usize] {
+        &self.calls
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    /// Writes the heap, in the format of [`Heap::write_to`], so that it can be
+    /// restored with [`Vm::load_heap`].
+    #[inline]
+    pub fn dump_heap<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.heap.write_to(w)
+    }
+
+    /// Replaces the heap with one written by [`Vm::dump_heap`].
+    #[inline]
+    pub fn load_heap<R: Read + ?Sized>(&mut self, r: &mut R) -> io::Result<()> {
+        self.heap = Heap::read_from(r)?;
+        Ok(())
+    }
+
+    /// Registers a callback, that is called with the value whenever `addr` is
+    /// stored to, replacing any previous callback for that address. Callbacks
+    /// cannot be cloned, so a clone of the machine has no watches.
+    #[inline]
+    pub fn set_heap_watch(&mut self, addr: Integer, cb: HeapWatch) {
+        self.watches
+            .get_or_insert_with(Watches::default)
+            .set_heap(addr, cb);
+    }
+
+    /// Registers a callback, that is called with the new depth whenever an
+    /// instruction changes the stack depth from below `depth` to at least
+    /// `depth`, or the reverse, replacing any previous callback for that
+    /// depth. As with [`Vm::set_heap_watch`], a clone has no watches.
+    #[inline]
+    pub fn set_stack_watch(&mut self, depth: usize, cb: StackWatch) {
+        self.watches
+            .get_or_insert_with(Watches::default)
+            .set_stack(depth, cb);
+    }
+
+    /// Removes all watchpoints.
+    #[inline]
+    pub fn clear_watches(&mut self) {
+        self.watches = None;
+    }
+
+    /// Traces execution to a writer, with a line for each instruction before
+    /// it executes, and returns the previous writer. `None` disables tracing,
+    /// which is the default.
+    ///
+    /// Each line has four tab-separated fields: the index of the instruction,
+    /// the instruction with its argument, the stack depth, and the top of the
+    /// stack, or `-`, when it is empty. Integer arguments are decoded with
+    /// the number encoding of the machine and written in decimal, and labels
+    /// are written as in assembly, for example `7\tpush -3\t2\t10`.
+    ///
+    /// Like watches, the writer is not kept by a clone of the machine.
+    #[inline]
+    pub fn set_trace_writer(&mut self, writer: Option<Box<dyn Write>>) -> Option<Box<dyn Write>> {
+        self.trace.set(writer)
+    }
+
+    /// Sets a breakpoint before the instruction at index `pc`.
+    #[inline]
+    pub fn set_breakpoint(&mut self, pc: usize) {
+        self.breakpoints.insert(pc);
+    }
+
+    /// Sets a breakpoint at the definition of a label and returns its index,
+    /// or an error, if the label is not defined.
+    pub fn break_at_label(&mut self, label: &BitSlice) -> Result<usize, RuntimeError> {
+        let pc = (self.labels.get(label).copied())
+            .ok_or_else(|| RuntimeError::UndefinedLabel(label.to_bitvec()))?;
+        self.set_breakpoint(pc);
+        Ok(pc)
+    }
+
+    /// Removes all breakpoints.
+    #[inline]
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.at_breakpoint = false;
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Executes the program until it halts, traps, or stops at a breakpoint,
+    /// then flushes the output. The output is flushed even when the program
+    /// traps, in which case the trap is returned over any error from
+    /// flushing.
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<(), RuntimeError> {
+        let res = loop {
+            match self.step(input, output) {
+                Ok(StepResult::Continue) => {}
+                Ok(StepResult::Halted | StepResult::Breakpoint) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        };
+        let flushed = output.flush();
+        res.and(flushed.map_err(RuntimeError::from))
+    }
+
+    /// Executes the program with the given input until it halts or traps, and
+    /// returns its output.
+    pub fn run_capture(&mut self, mut input: &[u8]) -> Result<Vec<u8>, RuntimeError> {
+        let mut output = Vec::new();
+        self.run(&mut input, &mut output)?;
+        Ok(output)
+    }
+
+    /// Executes the program with the given input until it halts or traps, and
+    /// returns its output and the number of input bytes consumed.
+    ///
+    /// This works only with byte slices, for embedding where streams are
+    /// awkward, such as calling from JavaScript in WebAssembly. It needs no
+    /// `wasm` feature gate, because `std::io` is available on
+    /// `wasm32-unknown-unknown` and the in-memory readers and writers used here
+    /// work there.
+    pub fn run_with_slices(&mut self, input: &[u8]) -> Result<(Vec<u8>, usize), RuntimeError> {
+        let mut rest = input;
+        let mut output = Vec::new();
+        self.run(&mut rest, &mut output)?;
+        Ok((output, input.len() - rest.len()))
+    }
+
+    /// Executes a single instruction. When the machine traps, its state is
+    /// left as it was before the instruction, and the error is
+    /// [`RuntimeError::At`] the index of the instruction.
+    ///
+    /// Input that a trapping `readi` has read is not restored, nor is invalid
+    /// UTF-8 that `readc` had to read past the end of the buffer of `input`.
+    ///
+    /// When the instruction has a breakpoint, execution first stops with
+    /// [`StepResult::Breakpoint`], then the next step executes it.
+    pub fn step<R: BufRead, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<StepResult, RuntimeError> {
+        if self.halted {
+            return Ok(StepResult::Halted);
+        }
+        if !mem::take(&mut self.at_breakpoint) && self.breakpoints.contains(&self.pc) {
+            self.at_breakpoint = true;
+            return Ok(StepResult::Breakpoint);
+        }
+        if self.pc >= self.insts.len() {
+            return match self.options.on_fallthrough {
+                Fallthrough::Halt => {
+                    self.halted = true;
+                    Ok(StepResult::Halted)
+                }
+                Fallthrough::Error => Err(RuntimeError::NoEnd.at(self.pc)),
+            };
+        }
+        (self.trace.record(
+            self.pc,
+            &self.insts[self.pc],
+            &self.stack,
+            self.options.number_encoding,
+        ))
+        .map_err(|err| RuntimeError::from(err).at(self.pc))?;
+        // Move the instructions out, so that the instruction can be borrowed
+        // while executing.
+        let insts = mem::take(&mut self.insts);
+        let res = self.exec(&insts[self.pc], input, output);
+        self.insts = insts;
+        res.map_err(|err| err.at(self.pc))
+    }
+
+    /// Executes a single instruction against the current state, without it
+    /// being part of the program, for interactive use like a calculator.
+    ///
+    /// Only instructions that operate on the stack and heap are allowed:
+    /// `push`, `dup`, `copy`, `swap`, `drop`, `slide`, `add`, `sub`, `mul`,
+    /// `div`, `mod`, `store`, and `retrieve`. Control flow needs the program
+    /// and I/O needs streams, so those instructions, and the rest, trap with
+    /// [`RuntimeError::Unsupported`]. The program counter is not changed.
+    pub fn exec_one(&mut self, inst: &RawInst) -> Result<(), RuntimeError> {
+        match inst {
+            Inst::Push(_)
+            | Inst::Dup
+            | Inst::Copy(_)
+            | Inst::Swap
+            | Inst::Drop
+            | Inst::Slide(_)
+            | Inst::Add
+            | Inst::Sub
+            | Inst::Mul
+            | Inst::Div
+            | Inst::Mod
+            | Inst::Store
+            | Inst::Retrieve => {}
+            Inst::Error(err) => return Err(RuntimeError::InvalidInst(err.clone())),
+            _ => return Err(RuntimeError::Unsupported(inst.opcode())),
+        }
+        let pc = self.pc;
+        let res = self.exec(inst, &mut io::empty(), &mut io::sink());
+        self.pc = pc;
+        res.map(|_| ())
+    }
+
+    fn exec<R: BufRead, W: Write>(
+        &mut self,
+        inst: &RawInst,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<StepResult, RuntimeError> {
+        if self.watches.is_none() {
+            return self.exec_inst(inst, input, output);
+        }
+        let depth = self.stack.len();
+        let res = self.exec_inst(inst, input, output);
+        let new_depth = self.stack.len();
+        if let Some(watches) = &mut self.watches {
+            watches.resize_stack(depth, new_depth);
+        }
+        res
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn exec_inst<R: BufRead, W: Write>(
+        &mut self,
+        inst: &RawInst,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<StepResult, RuntimeError> {
+        let mut next = self.pc + 1;
+        match inst {
+            Inst::Push(n) => self.stack.push(self.options.number_encoding.decode(n)),
+            Inst::Dup => {
+                let x = self.peek(0)?.clone();
+                self.stack.push(x);
+            }
+            Inst::Copy(n) => {
+                let n = stack_index(n, self.options.number_encoding)?;
+                let x = if n < self.stack.len() {
+                    self.peek(n)?.clone()
+                } else {
+                    match self.options.stack_index {
+                        StackIndexPolicy::Strict => return Err(RuntimeError::StackIndexOutOfRange),
+                        StackIndexPolicy::Lenient => Integer::ZERO,
+                    }
+                };
+                self.stack.push(x);
+            }
+            Inst::Swap => {
+                self.check_len(2)?;
+                let len = self.stack.len();
+                self.stack.swap(len - 1, len - 2);
+            }
+            Inst::Drop => {
+                self.pop()?;
+            }
+            Inst::Slide(n) => {
+                let mut n = stack_index(n, self.options.number_encoding)?;
+                self.check_len(1)?;
+                if n >= self.stack.len() {
+                    match self.options.stack_index {
+                        StackIndexPolicy::Strict => return Err(RuntimeError::StackIndexOutOfRange),
+                        StackIndexPolicy::Lenient => n = self.stack.len() - 1,
+                    }
+                }
+                let x = self.pop()?;
+                self.stack.truncate(self.stack.len() - n);
+                self.stack.push(x);
+            }
+            Inst::Add => self.arith(|x, y| Ok(x + y))?,
+            Inst::Sub => self.arith(|x, y| Ok(x - y))?,
+            Inst::Mul => self.arith(|x, y| Ok(x * y))?,
+            Inst::Div => {
+                let mode = self.options.div_mode;
+                self.arith(|x, y| {
+                    if y == 0 {
+                        return Err(RuntimeError::DivisionByZero);
+                    }
+                    Ok(match mode {
+                        DivMode::Floor => x.div_floor(y),
+                        DivMode::Truncate => x.div_trunc(y),
+                    })
+                })?;
+            }
+            Inst::Mod => {
+                let mode = self.options.div_mode;
+                self.arith(|x, y| {
+                    if y == 0 {
+                        return Err(RuntimeError::DivisionByZero);
+                    }
+                    Ok(match mode {
+                        DivMode::Floor => x.rem_floor(y),
+                        DivMode::Truncate => x.rem_trunc(y),
+                    })
+                })?;
+            }
+            Inst::Store => {
+                self.check_len(2)?;
+                self.check_heap(self.peek(1)?)?;
+                let value = self.pop()?;
+                let addr = self.pop()?;
+                self.store(addr, value);
+            }
+            Inst::Retrieve => {
+                let addr = self.peek(0)?;
+                let value = self.heap.get(addr).cloned().unwrap_or_default();
+                *self.stack.last_mut().unwrap() = value;
+            }
+            Inst::Label(_) => {}
+            Inst::Call(l) => {
+                if matches!(self.options.max_call_depth, Some(max) if self.calls.len() >= max) {
+                    return Err(RuntimeError::CallStackOverflow);
+                }
+                next = self.label(l)?;
+                self.calls.push(self.pc + 1);
+            }
+            Inst::Jmp(l) => next = self.label(l)?,
+            Inst::Jz(l) | Inst::Jn(l) => {
+                let x = self.peek(0)?;
+                let jump = if matches!(inst, Inst::Jz(_)) {
+                    *x == 0
+                } else {
+                    *x < 0
+                };
+                if jump {
+                    next = self.label(l)?;
+                }
+                self.stack.pop();
+            }
+            Inst::Ret => next = self.calls.pop().ok_or(RuntimeError::RetWithoutCall)?,
+            Inst::End => {
+                self.halted = true;
+                return Ok(StepResult::Halted);
+            }
+            Inst::Printc => {
+                let x = self.peek(0)?;
+                let Some(ch) = x.to_u32().and_then(char::from_u32) else {
+                    return Err(RuntimeError::InvalidChar(x.clone()));
+                };
+                write!(output, "{ch}")?;
+                self.flush_write(output)?;
+                self.stack.pop();
+            }
+            Inst::Printi => {
+                let x = self.peek(0)?;
+                output.write_all(self.options.printi.format(x).as_bytes())?;
+                self.flush_write(output)?;
+                self.stack.pop();
+            }
+            Inst::Readc => {
+                self.check_heap(self.peek(0)?)?;
+                let value = match read_char(input)? {
+                    Some(ch) => Integer::from(u32::from(ch)),
+                    None => self.on_eof()?,
+                };
+                let addr = self.stack.pop().unwrap();
+                self.store(addr, value);
+            }
+            Inst::Readi => {
+                self.check_heap(self.peek(0)?)?;
+                let n = match read_integer(input)? {
+                    Some(n) => n,
+                    None => self.on_eof()?,
+                };
+                let addr = self.stack.pop().unwrap();
+                self.store(addr, n);
+            }
+            Inst::Shuffle | Inst::DumpStack | Inst::DumpHeap | Inst::DumpTrace => {
+                return Err(RuntimeError::Unsupported(inst.opcode()));
+            }
+            Inst::Error(err) => return Err(RuntimeError::InvalidInst(err.clone())),
+        }
+        self.pc = next;
+        Ok(StepResult::Continue)
+    }
+
+    /// The value read at EOF, if the EOF policy allows it.
+    fn on_eof(&self) -> Result<Integer, RuntimeError> {
+        match self.options.on_eof {
+            EofPolicy::Error => Err(RuntimeError::InputExhausted),
+            EofPolicy::Zero => Ok(Integer::ZERO),
+            EofPolicy::NegOne => Ok(Integer::from(-1)),
+        }
+    }
+
+    /// Flushes the output after a write, if the flush policy requires it.
+    #[inline]
+    fn flush_write<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        match self.options.flush {
+            FlushPolicy::EachWrite => output.flush(),
+            FlushPolicy::OnHalt => Ok(()),
+        }
+    }
+
+    /// Stores to the heap and fires any watch for the address.
+    #[inline]
+    fn store(&mut self, addr: Integer, value: Integer) {
+        if let Some(watches) = &mut self.watches {
+            watches.store(&addr, &value);
+        }
+        self.heap.store(addr, value);
+    }
+
+    /// Checks that storing to `addr` would not exceed the heap limit.
+    #[inline]
+    fn check_heap(&self, addr: &Integer) -> Result<(), RuntimeError> {
+        match self.options.max_heap_cells {
+            Some(max) if self.heap.len() >= max && self.heap.get(addr).is_none() => {
+                Err(RuntimeError::HeapLimitExceeded)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    #[inline]
+    fn check_len(&self, n: usize) -> Result<(), RuntimeError> {
+        if self.stack.len() < n {
+            return Err(RuntimeError::StackUnderflow {
+                depth: self.stack.len(),
+                needed: n,
+            });
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn peek(&self, n: usize) -> Result<&Integer, RuntimeError> {
+        self.check_len(n + 1)?;
+        Ok(&self.stack[self.stack.len() - n - 1])
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Result<Integer, RuntimeError> {
+        self.check_len(1)?;
+        Ok(self.stack.pop().unwrap())
+    }
+
+    fn arith<F>(&mut self, f: F) -> Result<(), RuntimeError>
+    where
+        F: FnOnce(Integer, Integer) -> Result<Integer, RuntimeError>,
+    {
+        self.check_len(2)?;
+        let y = self.peek(0)?.clone();
+        let x = self.peek(1)?.clone();
+        let z = f(x, y)?;
+        self.stack.truncate(self.stack.len() - 2);
+        self.stack.push(z);
+        Ok(())
+    }
+
+    #[inline]
+    fn label(&self, l: &BitVec) -> Result<usize, RuntimeError> {
+        (self.labels.get(l).copied()).ok_or_else(|| RuntimeError::UndefinedLabel(l.clone()))
+    }
+}
+
+/// Converts the operand of `copy` or `slide`, saturating indices that are too
+/// large for `usize`, which are beyond any stack.
+fn stack_index(n: &BitVec, encoding: NumberEncoding) -> Result<usize, RuntimeError> {
+    let n = encoding.decode(n);
+    if n < 0 {
+        return Err(RuntimeError::StackIndexOutOfRange);
+    }
+    Ok(n.to_usize().unwrap_or(usize::MAX))
+}
+
+/// Reads a UTF-8 encoded char, or `None` at EOF.
+///
+/// Invalid UTF-8 is left unconsumed when the whole sequence is buffered, so
+/// that the trap does not change the input. A sequence that spans the end of
+/// the buffer must be read to be decoded, so it is consumed either way.
+fn read_char<R: BufRead>(input: &mut R) -> Result<Option<char>, RuntimeError> {
+    let buf = input.fill_buf()?;
+    let first = match buf.first() {
+        Some(&b) => b,
+        None => return Ok(None),
+    };
+    let width = utf8_width(first);
+    if buf.len() >= width {
+        let ch = decode_char(&buf[..width])?;
+        input.consume(width);
+        return Ok(Some(ch));
+    }
+    let mut buf = [0; 4];
+    let mut len = 0;
+    while len < width {
+        let n = input.read(&mut buf[len..width])?;
+        if n == 0 {
+            break;
+        }
+        len += n;
+    }
+    decode_char(&buf[..len]).map(Some)
+}
+
+/// Decodes bytes that should be exactly one UTF-8 encoded char.
+fn decode_char(bytes: &[u8]) -> Result<char, RuntimeError> {
+    match decode_utf8(bytes) {
+        (Some(ch), size) if size == bytes.len() => Ok(ch),
+        (_, size) => {
+            let bad = ArrayVec::try_from(&bytes[..size.clamp(1, 3)]).unwrap();
+            Err(RuntimeError::InvalidUtf8(EncodingError::InvalidUtf8(bad)))
+        }
+    }
+}
+
+/// The length of a UTF-8 sequence, from its leading byte.
+fn utf8_width(first: u8) -> usize {
+    match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        // ASCII and invalid leading bytes
+        _ => 1,
+    }
+}
+
+/// Reads a line and parses it as a decimal integer, ignoring surrounding
+/// whitespace, or returns `None` at EOF.
+fn read_integer<R: BufRead>(input: &mut R) -> Result<Option<Integer>, RuntimeError> {
+    let mut line = Vec::new();
+    if input.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    let line = String::from_utf8_lossy(&line);
+    Integer::parse(line.trim())
+        .map(|n| Some(Integer::from(n)))
+        .map_err(|_| RuntimeError::InvalidInt(line.into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::cmp::Ordering;
+    use std::fmt::Debug;
+    use std::rc::Rc;
+
+    use bitvec::prelude::*;
+
+    use super::*;
+    use crate::ws::inst::{InstError, Opcode};
+    use crate::ws::parse::ParseError;
+    use crate::ws::tests::{int_arg, lcg, push};
+
+    fn trap<T: Debug>(res: &Result<T, RuntimeError>) -> &RuntimeError {
+        res.as_ref().unwrap_err().kind()
+    }
+
+    fn run(insts: Vec<RawInst>, input: &str) -> (Vm, Result<(), RuntimeError>, String) {
+        let mut vm = Vm::new(insts);
+        let mut output = Vec::new();
+        let res = vm.run(&mut input.as_bytes(), &mut output);
+        (vm, res, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn arith() {
+        for (x, y, div, rem) in [
+            (7, 2, 3, 1),
+            (-7, 2, -4, 1),
+            (7, -2, -4, -1),
+            (-7, -2, 3, -1),
+        ] {
+            let (vm, res, _) = run(
+                vec![
+                    push(x),
+                    push(y),
+                    Inst::Div,
+                    push(x),
+                    push(y),
+                    Inst::Mod,
+                    Inst::End,
+                ],
+                "",
+            );
+            res.unwrap();
+            assert_eq!([div, rem], vm.stack(), "{x} / {y}");
+        }
+        let (vm, res, _) = run(vec![push(1), push(0), Inst::Div, Inst::End], "");
+        assert!(matches!(trap(&res), RuntimeError::DivisionByZero));
+        assert_eq!([1, 0], vm.stack());
+    }
+
+    #[test]
+    fn div_modes() {
+        // (x, y, floor div, floor mod, trunc div, trunc mod)
+        let cases = [
+            (7, 2, 3, 1, 3, 1),
+            (-7, 2, -4, 1, -3, -1),
+            (7, -2, -4, -1, -3, 1),
+            (-7, -2, 3, -1, 3, -1),
+            (6, 3, 2, 0, 2, 0),
+            (-6, 3, -2, 0, -2, 0),
+            (6, -3, -2, 0, -2, 0),
+            (0, 5, 0, 0, 0, 0),
+            (0, -5, 0, 0, 0, 0),
+            (1, 5, 0, 1, 0, 1),
+            (-1, 5, -1, 4, 0, -1),
+        ];
+        for (x, y, floor_div, floor_mod, trunc_div, trunc_mod) in cases {
+            for (mode, div, rem) in [
+                (DivMode::Floor, floor_div, floor_mod),
+                (DivMode::Truncate, trunc_div, trunc_mod),
+            ] {
+                let insts = vec![
+                    push(x),
+                    push(y),
+                    Inst::Div,
+                    push(x),
+                    push(y),
+                    Inst::Mod,
+                    Inst::End,
+                ];
+                let options = VmOptions {
+                    div_mode: mode,
+                    ..VmOptions::default()
+                };
+                let mut vm = Vm::with_options(insts, options);
+                vm.run_capture(b"").unwrap();
+                assert_eq!([div, rem], vm.stack(), "{x} / {y} with {mode:?}");
+                assert_eq!(x, div * y + rem);
+            }
+        }
+
+        for mode in [DivMode::Floor, DivMode::Truncate] {
+            for op in [Inst::Div, Inst::Mod] {
+                let insts = vec![push(-3), push(0), op, Inst::End];
+                let options = VmOptions {
+                    div_mode: mode,
+                    ..VmOptions::default()
+                };
+                let mut vm = Vm::with_options(insts, options);
+                let res = vm.run_capture(b"");
+                assert!(matches!(trap(&res), RuntimeError::DivisionByZero));
+                assert_eq!([-3, 0], vm.stack());
+            }
+        }
+    }
+
+    #[test]
+    fn io() {
+        let insts = vec![
+            push(0),
+            Inst::Readc,
+            push(1),
+            Inst::Readi,
+            push(0),
+            Inst::Retrieve,
+            Inst::Printc,
+            push(1),
+            Inst::Retrieve,
+            Inst::Printi,
+            push(2),
+            Inst::Retrieve,
+            Inst::Printi,
+            Inst::End,
+        ];
+        let (vm, res, output) = run(insts, "\u{3bb} -42 \n");
+        res.unwrap();
+        assert_eq!("\u{3bb}-420", output);
+        assert_eq!(2, vm.heap().len());
+
+        let mut vm = Vm::new(vec![
+            push(0),
+            Inst::Readi,
+            push(0),
+            Inst::Retrieve,
+            Inst::Printi,
+            Inst::End,
+        ]);
+        let (output, consumed) = vm.run_with_slices(b"12\n34\n").unwrap();
+        assert_eq!((&b"12"[..], 3), (&output[..], consumed));
+
+        let (_, res, _) = run(vec![push(0), Inst::Readi, Inst::End], "x\n");
+        assert!(matches!(trap(&res), RuntimeError::InvalidInt(_)));
+        let (_, res, _) = run(vec![push(0), Inst::Readc, Inst::End], "");
+        assert!(matches!(trap(&res), RuntimeError::InputExhausted));
+
+        // Invalid UTF-8 is left unread.
+        let mut vm = Vm::new(vec![push(0), Inst::Readc, Inst::End]);
+        let mut input = &b"\xce\xff"[..];
+        let res = vm.run(&mut input, &mut Vec::new());
+        assert!(matches!(trap(&res), RuntimeError::InvalidUtf8(_)));
+        assert_eq!(b"\xce\xff", input);
+        assert_eq!(Some(1), res.unwrap_err().pc());
+    }
+
+    #[test]
+    fn flush_policy() {
+        /// Records the length of the output at each flush.
+        #[derive(Default)]
+        struct Flushes {
+            buf: Vec<u8>,
+            flushes: Vec<usize>,
+        }
+        impl Write for Flushes {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.buf.write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                self.flushes.push(self.buf.len());
+                Ok(())
+            }
+        }
+
+        /// Fails to flush, after recording the attempt.
+        #[derive(Default)]
+        struct FailFlush(usize);
+        impl Write for FailFlush {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                self.0 += 1;
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+        }
+
+        let insts = vec![push(97), Inst::Printc, push(42), Inst::Printi, Inst::End];
+        for (flush, expected) in [
+            (FlushPolicy::EachWrite, &[1, 3, 3][..]),
+            (FlushPolicy::OnHalt, &[3][..]),
+        ] {
+            let options = VmOptions { flush, ..VmOptions::default() };
+            let mut vm = Vm::with_options(insts.clone(), options);
+            let mut output = Flushes::default();
+            vm.run(&mut &b""[..], &mut output).unwrap();
+            assert_eq!(b"a42", &output.buf[..]);
+            assert_eq!(expected, output.flushes, "{flush:?}");
+        }
+
+        // A trap is returned over an error from flushing.
+        let mut output = FailFlush::default();
+        let err = Vm::new(vec![Inst::Drop]).run(&mut &b""[..], &mut output);
+        assert!(matches!(trap(&err), RuntimeError::StackUnderflow { .. }));
+        assert_eq!(1, output.0);
+        let err = Vm::new(vec![Inst::End]).run(&mut &b""[..], &mut output);
+        assert!(matches!(err, Err(RuntimeError::Io(_))));
+    }
+
+    #[test]
+    fn heapless() {
+        let insts = vec![push(2), push(3), Inst::Mul, Inst::Printi, Inst::End];
+        let (mut vm, res, out) = run(insts, "");
+        res.unwrap();
+        assert_eq!("6", out);
+        assert!(vm.heap().is_empty());
+        assert_eq!(&Heap::new(), vm.heap());
+        assert_eq!("{}", format!("{:?}", vm.heap()));
+        let mut dump = Vec::new();
+        vm.dump_heap(&mut dump).unwrap();
+        assert_eq!(0u64.to_le_bytes(), &dump[..]);
+
+        // Storing outside the program still works.
+        vm.exec_one(&push(1)).unwrap();
+        vm.exec_one(&push(7)).unwrap();
+        vm.exec_one(&Inst::Store).unwrap();
+        assert_eq!(Some(&Integer::from(7)), vm.heap().get(&Integer::from(1)));
+    }
+
+    #[test]
+    fn dump_load_heap() {
+        let mut vm = Vm::new(Vec::new());
+        let mut addr = Integer::from(1);
+        for i in 0..3000 {
+            addr = addr * 7 % Integer::from(Integer::u_pow_u(10, 40));
+            let value = Integer::from(i - 1500) << (i % 200);
+            vm.heap.store(Integer::from(&addr - 1500), value);
+        }
+        let mut dump = Vec::new();
+        vm.dump_heap(&mut dump).unwrap();
+
+        let mut loaded = Vm::new(Vec::new());
+        loaded.load_heap(&mut &dump[..]).unwrap();
+        assert_eq!(vm.heap(), loaded.heap());
+        assert!(loaded.load_heap(&mut &dump[..dump.len() - 1]).is_err());
+
+        // The same cells stored in a different order dump identically.
+        let mut reversed = Vm::new(Vec::new());
+        let mut cells = vm.heap().iter().collect::<Vec<_>>();
+        cells.reverse();
+        for (addr, value) in cells {
+            reversed.heap.store(addr.clone(), value.clone());
+        }
+        let mut reversed_dump = Vec::new();
+        reversed.dump_heap(&mut reversed_dump).unwrap();
+        assert_eq!(dump, reversed_dump);
+        assert_eq!(format!("{:?}", vm.heap()), format!("{:?}", reversed.heap()));
+        let addrs = vm
+            .heap()
+            .iter_sorted()
+            .map(|(addr, _)| addr)
+            .collect::<Vec<_>>();
+        assert!(addrs.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn with_state() {
+        let mut heap = Heap::new();
+        heap.store(Integer::from(3), Integer::from(42));
+        let insts = vec![
+            push(99),
+            Inst::Printi,
+            Inst::Retrieve,
+            Inst::Printi,
+            Inst::End,
+        ];
+        let mut vm = Vm::with_state(insts, vec![Integer::from(3)], heap);
+        vm.set_pc(2);
+        assert_eq!(b"42", &vm.run_capture(b"").unwrap()[..]);
+        assert!(vm.stack().is_empty());
+    }
+
+    #[test]
+    fn deterministic() {
+        let mut insts = Vec::new();
+        for i in 0..50 {
+            insts.extend([push(i), push(i * 7 - 100), Inst::Store]);
+        }
+        insts.extend([push(1000), Inst::Readi]);
+        for i in (0..50).chain([1000]).rev() {
+            insts.extend([push(i), Inst::Retrieve, Inst::Printi, push(i)]);
+        }
+        insts.push(Inst::End);
+
+        // Each machine has its own heap, with a differently-seeded hasher.
+        let (vm1, res1, output1) = run(insts.clone(), "42\n");
+        let (vm2, res2, output2) = run(insts, "42\n");
+        res1.unwrap();
+        res2.unwrap();
+        assert_eq!(output1, output2);
+        assert_eq!(vm1.stack(), vm2.stack());
+        assert_eq!(vm1.heap(), vm2.heap());
+        assert_eq!(format!("{:?}", vm1.heap()), format!("{:?}", vm2.heap()));
+        let (mut dump1, mut dump2) = (Vec::new(), Vec::new());
+        vm1.dump_heap(&mut dump1).unwrap();
+        vm2.dump_heap(&mut dump2).unwrap();
+        assert_eq!(dump1, dump2);
+    }
+
+    #[test]
+    fn fallthrough() {
+        let insts = vec![push(1), Inst::Printi];
+        let mut vm = Vm::new(insts.clone());
+        assert!(matches!(trap(&vm.run_capture(b"")), RuntimeError::NoEnd));
+        let options = VmOptions {
+            on_fallthrough: Fallthrough::Halt,
+            ..VmOptions::default()
+        };
+        let mut vm = Vm::with_options(insts, options);
+        assert_eq!(b"1", &vm.run_capture(b"").unwrap()[..]);
+        assert!(vm.is_halted());
+    }
+
+    #[test]
+    fn div_mod_identity() {
+        let mut rand = lcg();
+        let signed = |n: u64| Integer::from(n) - (Integer::from(1) << 63);
+        for mode in [DivMode::Floor, DivMode::Truncate] {
+            let options = VmOptions {
+                div_mode: mode,
+                ..VmOptions::default()
+            };
+            for _ in 0..1000 {
+                // Include operands beyond 64 bits and small divisors.
+                let x = signed(rand()) << u32::try_from(rand() % 80).unwrap();
+                let x = x + signed(rand());
+                let y = signed(rand()) >> u32::try_from(rand() % 64).unwrap();
+                if y == 0 {
+                    continue;
+                }
+                let mut vm = Vm::with_options(Vec::new(), options);
+                for inst in [push(&x), push(&y), Inst::Div, push(&x), push(&y), Inst::Mod] {
+                    vm.exec_one(&inst).unwrap();
+                }
+                let [div, rem] = vm.stack() else {
+                    unreachable!()
+                };
+                assert_eq!(x, Integer::from(div * &y) + rem, "{x} / {y} with {mode:?}");
+                assert!(rem.clone().abs() < y.clone().abs());
+                let sign = match mode {
+                    DivMode::Floor => y.cmp0(),
+                    DivMode::Truncate => x.cmp0(),
+                };
+                assert!(rem.cmp0() == sign || rem.cmp0() == Ordering::Equal);
+            }
+        }
+        let mut vm = Vm::new(Vec::new());
+        for inst in [push(7), push(0)] {
+            vm.exec_one(&inst).unwrap();
+        }
+        assert!(matches!(
+            vm.exec_one(&Inst::Mod),
+            Err(RuntimeError::DivisionByZero)
+        ));
+    }
+
+    #[test]
+    fn transcript() {
+        use IoEvent::{In, Out};
+
+        // Prompt, then echo a line.
+        let mut vm = Vm::new(vec![
+            push('?' as i32),
+            Inst::Printc,
+            push(0),
+            Inst::Readi,
+            push(0),
+            Inst::Retrieve,
+            Inst::Printi,
+            push('\n' as i32),
+            Inst::Printc,
+            Inst::End,
+        ]);
+        let (transcript, res) = vm.run_transcript(b"42\nrest");
+        res.unwrap();
+        let expected = [
+            Out(b'?'),
+            In(b'4'),
+            In(b'2'),
+            In(b'\n'),
+            Out(b'4'),
+            Out(b'2'),
+            Out(b'\n'),
+        ];
+        assert_eq!(&expected[..], transcript.events);
+        assert_eq!(
+            "> \"?\"\n< \"42\\n\"\n> \"42\\n\"\n",
+            transcript.to_string()
+        );
+    }
+
+    #[test]
+    fn eof_policy() {
+        let insts = vec![
+            push(0),
+            Inst::Readc,
+            push(1),
+            Inst::Readi,
+            push(0),
+            Inst::Retrieve,
+            Inst::Printi,
+            push(1),
+            Inst::Retrieve,
+            Inst::Printi,
+            Inst::End,
+        ];
+        let mut vm = Vm::new(insts.clone());
+        let err = vm.run_capture(b"").unwrap_err();
+        assert_eq!(Some(1), err.pc());
+        assert_eq!("at instruction 1: input exhausted", err.to_string());
+        for (on_eof, expected) in [(EofPolicy::Zero, "00"), (EofPolicy::NegOne, "-1-1")] {
+            let options = VmOptions { on_eof, ..VmOptions::default() };
+            let mut vm = Vm::with_options(insts.clone(), options);
+            assert_eq!(expected.as_bytes(), &vm.run_capture(b"").unwrap()[..]);
+        }
+    }
+
+    #[test]
+    fn resume() {
+        // Echo a char, then the sum of two integers.
+        let mut vm = Vm::new(vec![
+            push(0),
+            Inst::Readc,
+            push(0),
+            Inst::Retrieve,
+            Inst::Printc,
+            push(1),
+            Inst::Readi,
+            push(2),
+            Inst::Readi,
+            push(1),
+            Inst::Retrieve,
+            push(2),
+            Inst::Retrieve,
+            Inst::Add,
+            Inst::Printi,
+            Inst::End,
+        ]);
+        assert!(matches!(vm.resume(b""), StepOutcome::NeedInput));
+        assert_eq!(1, vm.pc());
+        // Wait for the rest of a multi-byte char and a whole line.
+        assert!(matches!(vm.resume(b"\xce"), StepOutcome::NeedInput));
+        assert!(matches!(vm.resume(b"\xbb1"), StepOutcome::NeedInput));
+        assert_eq!("λ", String::from_utf8(vm.take_output()).unwrap());
+        assert!(matches!(vm.resume(b"2\n3"), StepOutcome::NeedInput));
+        assert_eq!(8, vm.pc());
+        assert!(vm.take_output().is_empty());
+        vm.end_input();
+        assert!(matches!(vm.resume(b""), StepOutcome::Halted));
+        assert_eq!(b"15", &vm.take_output()[..]);
+
+        let mut vm = Vm::new(vec![push(0), Inst::Readi, Inst::End]);
+        vm.end_input();
+        assert!(matches!(
+            vm.resume(b""),
+            StepOutcome::Trapped(RuntimeError::At { pc: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn exec_one() {
+        let mut vm = Vm::new(Vec::new());
+        for inst in [push(6), push(7), Inst::Mul, Inst::Dup, push(2), Inst::Sub] {
+            vm.exec_one(&inst).unwrap();
+        }
+        assert_eq!([42, 40], vm.stack());
+        assert_eq!(0, vm.pc());
+        assert!(matches!(
+            vm.exec_one(&Inst::Printi),
+            Err(RuntimeError::Unsupported(Opcode::Printi)),
+        ));
+        assert!(matches!(
+            vm.exec_one(&Inst::Jmp(BitVec::new())),
+            Err(RuntimeError::Unsupported(Opcode::Jmp)),
+        ));
+    }
+
+    #[test]
+    fn watches() {
+        let insts = vec![
+            push(5),
+            push(10),
+            Inst::Store,
+            push(6),
+            push(1),
+            Inst::Store,
+            push(5),
+            push(3),
+            Inst::Store,
+            push(5),
+            Inst::Readc,
+            Inst::End,
+        ];
+        let mut vm = Vm::new(insts);
+        let stores = Rc::new(RefCell::new(Vec::new()));
+        let depths = Rc::new(RefCell::new(Vec::new()));
+        let log = stores.clone();
+        vm.set_heap_watch(
+            Integer::from(5),
+            Box::new(move |value| log.borrow_mut().push(value.clone())),
+        );
+        let log = depths.clone();
+        vm.set_stack_watch(2, Box::new(move |depth| log.borrow_mut().push(depth)));
+        vm.clone().run_capture(b"A").unwrap();
+        assert!(stores.borrow().is_empty() && depths.borrow().is_empty());
+        vm.run_capture(b"A").unwrap();
+        assert_eq!([10, 3, 65], &stores.borrow()[..]);
+        assert_eq!([2, 0, 2, 0, 2, 0], &depths.borrow()[..]);
+    }
+
+    #[test]
+    fn trace_writer() {
+        #[derive(Clone, Default)]
+        struct Shared(Rc<RefCell<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let insts = vec![
+            push(-3),
+            Inst::Label(bitvec![0, 1]),
+            push(10),
+            Inst::Jmp(bitvec![1]),
+            Inst::Label(bitvec![1]),
+            Inst::End,
+        ];
+        let mut vm = Vm::new(insts.clone());
+        let log = Shared::default();
+        assert!(vm.set_trace_writer(Some(Box::new(log.clone()))).is_none());
+        vm.run_capture(b"").unwrap();
+        let expected = "0\tpush -3\t0\t-
+1\tlabel .01\t1\t-3
+2\tpush 10\t1\t-3
+3\tjmp 1\t2\t10
+4\tlabel 1\t2\t10
+5\tend\t2\t10
+";
+        assert_eq!(expected, String::from_utf8(log.0.take()).unwrap());
+        assert!(vm.set_trace_writer(None).is_some());
+
+        // Tracing does not change execution.
+        let mut untraced = Vm::new(insts);
+        untraced.run_capture(b"").unwrap();
+        assert_eq!(untraced.stack(), vm.clone().stack());
+        assert!(format!("{vm:?}").contains("enabled: false"));
+
+        // Parse errors are traced before they trap.
+        let err = ParseError::UnterminatedArg(Opcode::Push, bitvec![1]);
+        let mut vm = Vm::new(vec![Inst::Error(InstError::ParseError(err))]);
+        let log = Shared::default();
+        vm.set_trace_writer(Some(Box::new(log.clone())));
+        assert!(vm.run_capture(b"").is_err());
+        assert!(String::from_utf8(log.0.take())
+            .unwrap()
+            .starts_with("0\terror: "));
+    }
+
+    #[test]
+    fn stack_index_policies() {
+        let strict = VmOptions::default();
+        let lenient = VmOptions {
+            stack_index: StackIndexPolicy::Lenient,
+            ..VmOptions::default()
+        };
+        let run_with = |insts: Vec<RawInst>, options| {
+            let mut vm = Vm::with_options(insts, options);
+            let res = vm.run_capture(b"");
+            (vm, res)
+        };
+
+        let copy = vec![push(1), push(2), Inst::Copy(int_arg(5)), Inst::End];
+        let (vm, res) = run_with(copy.clone(), strict);
+        assert!(matches!(trap(&res), RuntimeError::StackIndexOutOfRange));
+        assert_eq!([1, 2], vm.stack());
+        let (vm, res) = run_with(copy, lenient);
+        res.unwrap();
+        assert_eq!([1, 2, 0], vm.stack());
+
+        let slide = vec![
+            push(1),
+            push(2),
+            push(3),
+            Inst::Slide(int_arg(5)),
+            Inst::End,
+        ];
+        let (vm, res) = run_with(slide.clone(), strict);
+        assert!(matches!(trap(&res), RuntimeError::StackIndexOutOfRange));
+        assert_eq!([1, 2, 3], vm.stack());
+        let (vm, res) = run_with(slide, lenient);
+        res.unwrap();
+        assert_eq!([3], vm.stack());
+
+        for options in [strict, lenient] {
+            for inst in [Inst::Copy(int_arg(-1)), Inst::Slide(int_arg(-1))] {
+                let (_, res) = run_with(vec![push(1), inst, Inst::End], options);
+                assert!(matches!(trap(&res), RuntimeError::StackIndexOutOfRange));
+            }
+            let (_, res) = run_with(vec![Inst::Slide(int_arg(0)), Inst::End], options);
+            assert!(matches!(trap(&res), RuntimeError::StackUnderflow { .. }));
+        }
+    }
+
+    #[test]
+    fn recursive_factorial() {
+        let (fact, base) = (bitvec![0], bitvec![1]);
+        let insts = |n| {
+            vec![
+                push(n),
+                Inst::Call(fact.clone()),
+                Inst::Printi,
+                Inst::End,
+                // fact: n -> n!
+                Inst::Label(fact.clone()),
+                Inst::Dup,
+                Inst::Jz(base.clone()),
+                Inst::Dup,
+                push(1),
+                Inst::Sub,
+                Inst::Call(fact.clone()),
+                Inst::Mul,
+                Inst::Ret,
+                Inst::Label(base.clone()),
+                Inst::Drop,
+                push(1),
+                Inst::Ret,
+            ]
+        };
+        let (vm, res, output) = run(insts(10), "");
+        res.unwrap();
+        assert_eq!("3628800", output);
+        assert!(vm.call_stack().is_empty());
+
+        let options = VmOptions {
+            max_call_depth: Some(5),
+            ..VmOptions::default()
+        };
+        let mut vm = Vm::with_options(insts(4), options);
+        assert_eq!(b"24", &vm.run_capture(b"").unwrap()[..]);
+        let mut vm = Vm::with_options(insts(5), options);
+        let res = vm.run_capture(b"");
+        assert!(matches!(trap(&res), RuntimeError::CallStackOverflow));
+        assert_eq!(5, vm.call_stack().len());
+    }
+
+    #[test]
+    fn printi_format() {
+        let insts = |n| vec![push(n), Inst::Printi, push(0), Inst::Printi, Inst::End];
+        let run_with = |n, printi| {
+            let options = VmOptions { printi, ..VmOptions::default() };
+            let mut vm = Vm::with_options(insts(n), options);
+            String::from_utf8(vm.run_capture(b"").unwrap()).unwrap()
+        };
+        let decimal = IntFormat::default();
+        assert_eq!(IntFormat::new(10, false), Some(decimal));
+        assert_eq!("-2540", run_with(-254, decimal));
+        assert_eq!("2540", run_with(254, decimal));
+        let hex = IntFormat::new(16, true).unwrap();
+        assert_eq!("-fe+0", run_with(-254, hex));
+        assert_eq!("+fe+0", run_with(254, hex));
+        let binary = IntFormat::new(2, false).unwrap();
+        assert_eq!("10100", run_with(10, binary));
+        assert_eq!(36, IntFormat::new(36, false).unwrap().base());
+        for base in [0, 1, 37, 256 + 10] {
+            assert_eq!(None, IntFormat::new(base, false), "base {base}");
+        }
+    }
+
+    #[test]
+    fn max_heap_cells() {
+        let store = |addr| [push(addr), push(addr * 10), Inst::Store];
+        let mut insts = Vec::new();
+        for addr in [1, 2, 3, 2, 1] {
+            insts.extend(store(addr));
+        }
+        insts.extend([push(4), Inst::Readc]);
+        insts.extend(store(4));
+        insts.push(Inst::End);
+        let options = VmOptions {
+            max_heap_cells: Some(3),
+            ..VmOptions::default()
+        };
+
+        // Overwriting stored addresses does not count against the limit.
+        let mut vm = Vm::with_options(insts.clone(), options);
+        let res = vm.run_capture(b"a");
+        assert!(matches!(trap(&res), RuntimeError::HeapLimitExceeded));
+        assert!(matches!(res, Err(RuntimeError::At { pc: 16, .. })));
+        assert_eq!(3, vm.heap().len());
+        assert_eq!([4], vm.stack());
+
+        let mut vm = Vm::with_options(insts, VmOptions {
+            max_heap_cells: Some(4),
+            ..options
+        });
+        vm.run_capture(b"a").unwrap();
+        assert_eq!(4, vm.heap().len());
+    }
+
+    #[test]
+    fn breakpoints() {
+        let (top, exit) = (bitvec![0], bitvec![1]);
+        let insts = vec![
+            push(2),
+            Inst::Label(top.clone()),
+            Inst::Dup,
+            Inst::Jz(exit.clone()),
+            push(1),
+            Inst::Sub,
+            Inst::Jmp(top.clone()),
+            Inst::Label(exit.clone()),
+            Inst::End,
+        ];
+        let mut vm = Vm::new(insts);
+        assert_eq!(1, vm.break_at_label(&top).unwrap());
+        vm.set_breakpoint(8);
+        assert!(matches!(
+            vm.break_at_label(bits![1, 1]),
+            Err(RuntimeError::UndefinedLabel(_)),
+        ));
+
+        // Each run stops at a breakpoint, then resumes past it.
+        let mut stops = Vec::new();
+        while !vm.is_halted() {
+            vm.run_capture(b"").unwrap();
+            let stack = vm.stack().iter().map(|n| n.to_i32().unwrap());
+            stops.push((vm.pc(), stack.collect::<Vec<_>>()));
+        }
+        let expected = vec![
+            (1, vec![2]),
+            (1, vec![1]),
+            (1, vec![0]),
+            (8, vec![0]),
+            (8, vec![0]),
+        ];
+        assert_eq!(expected, stops);
+
+        vm = Vm::new(vm.insts().to_vec());
+        vm.break_at_label(&exit).unwrap();
+        vm.clear_breakpoints();
+        vm.run_capture(b"").unwrap();
+        assert!(vm.is_halted());
+    }
+
+    #[test]
+    fn traps() {
+        let (_, res, _) = run(vec![push(1), Inst::Drop, Inst::Add], "");
+        let err = res.unwrap_err();
+        assert_eq!(Some(2), err.pc());
+        assert!(matches!(err.kind(), RuntimeError::StackUnderflow {
+            depth: 0,
+            needed: 2
+        },));
+        assert_eq!(
+            "at instruction 2: stack underflow: needed 2 values, but the stack had 0",
+            err.to_string(),
+        );
+        let (_, res, _) = run(vec![push(1)], "");
+        assert!(matches!(trap(&res), RuntimeError::NoEnd));
+        let (_, res, _) = run(vec![Inst::Ret], "");
+        assert!(matches!(trap(&res), RuntimeError::RetWithoutCall));
+        let (_, res, _) = run(vec![Inst::Jmp(BitVec::new())], "");
+        assert!(matches!(trap(&res), RuntimeError::UndefinedLabel(_)));
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use rug::Integer;
+
+use crate::ws::syntax::NumberEncoding;
+
+/// Options for the behavior of a [`Vm`](crate::ws::vm::Vm) in cases where
+/// implementations differ.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VmOptions {
+    /// What to do when execution continues past the last instruction.
+    pub on_fallthrough: Fallthrough,
+    /// How `div` and `mod` round.
+    pub div_mode: DivMode,
+    /// What to do when the index of `copy` or `slide` is beyond the stack.
+    pub stack_index: StackIndexPolicy,
+    /// When to flush the output.
+    pub flush: FlushPolicy,
+    /// The maximum number of active calls, beyond which `call` traps with
+    /// [`RuntimeError::CallStackOverflow`](crate::ws::vm::RuntimeError::CallStackOverflow),
+    /// or `None` for no limit.
+    pub max_call_depth: Option<usize>,
+    /// The maximum number of distinct heap addresses that can be stored to,
+    /// beyond which a store to a new address traps with
+    /// [`RuntimeError::HeapLimitExceeded`](crate::ws::vm::RuntimeError::HeapLimitExceeded),
+    /// or `None` for no limit. Cells loaded with the machine count towards it.
+    pub max_heap_cells: Option<usize>,
+    /// What `readc` and `readi` do when the input is exhausted.
+    pub on_eof: EofPolicy,
+    /// How the bits of integer arguments encode their values.
+    pub number_encoding: NumberEncoding,
+    /// How `printi` formats integers.
+    pub printi: IntFormat,
+}
+
+/// How `printi` formats integers. The default is decimal without a plus sign,
+/// like the reference interpreter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IntFormat {
+    base: u8,
+    plus_sign: bool,
+}
+
+impl IntFormat {
+    /// Constructs a format with a base from 2 to 36, which uses lowercase
+    /// letters for digits above 9, or returns `None` for any other base. When
+    /// `plus_sign` is set, `+` is written before non-negative integers, like
+    /// `%+d`.
+    #[inline]
+    #[must_use]
+    pub fn new(base: u32, plus_sign: bool) -> Option<Self> {
+        let base = u8::try_from(base)
+            .ok()
+            .filter(|base| (2..=36).contains(base))?;
+        Some(IntFormat { base, plus_sign })
+    }
+
+    /// The base, from 2 to 36.
+    #[inline]
+    #[must_use]
+    pub const fn base(self) -> u32 {
+        self.base as u32
+    }
+
+    /// Whether `+` is written before non-negative integers.
+    #[inline]
+    #[must_use]
+    pub const fn plus_sign(self) -> bool {
+        self.plus_sign
+    }
+
+    /// Formats an integer, as `printi` writes it.
+    pub(super) fn format(self, n: &Integer) -> String {
+        let digits = n.to_string_radix(i32::from(self.base));
+        if self.plus_sign && *n >= 0 {
+            format!("+{digits}")
+        } else {
+            digits
+        }
+    }
+}
+
+impl Default for IntFormat {
+    #[inline]
+    fn default() -> Self {
+        IntFormat { base: 10, plus_sign: false }
+    }
+}
+
+/// What to do when execution continues past the last instruction, without an
+/// `end`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Fallthrough {
+    /// Halt, as if there were an implicit `end`.
+    Halt,
+    /// Trap with [`RuntimeError::NoEnd`](crate::ws::vm::RuntimeError::NoEnd).
+    #[default]
+    Error,
+}
+
+/// What `readc` and `readi` do when no input remains.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum EofPolicy {
+    /// Trap with
+    /// [`RuntimeError::InputExhausted`](crate::ws::vm::RuntimeError::InputExhausted).
+    #[default]
+    Error,
+    /// Store zero.
+    Zero,
+    /// Store -1, like C `getchar`.
+    NegOne,
+}
+
+/// How `div` and `mod` round, when the quotient is not exact.
+///
+/// In both modes, `x == (x div y) * y + (x mod y)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DivMode {
+    /// Round the quotient towards negative infinity, so the remainder has the
+    /// sign of the divisor, like Haskell `div` and `mod` in wspace.
+    #[default]
+    Floor,
+    /// Round the quotient towards zero, so the remainder has the sign of the
+    /// dividend, like C `/` and `%`.
+    Truncate,
+}
+
+/// What to do when the index of `copy` or `slide` is beyond the stack. A
+/// negative index is always an error.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum StackIndexPolicy {
+    /// Trap with
+    /// [`RuntimeError::StackIndexOutOfRange`](crate::ws::vm::RuntimeError::StackIndexOutOfRange).
+    #[default]
+    Strict,
+    /// `copy` pushes zero, as if the stack were padded with zeros, and
+    /// `slide` clamps the count to discard every value below the top.
+    Lenient,
+}
+
+/// When to flush the output writer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FlushPolicy {
+    /// Flush after each `printc` and `printi`, so that a prompt is visible
+    /// before the program waits on input, as for interactive programs.
+    EachWrite,
+    /// Flush only when the program halts or traps, which is faster when the
+    /// output is piped.
+    #[default]
+    OnHalt,
+}
@@ -0,0 +1,100 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::mem;
+
+use crate::ws::inst::Inst;
+use crate::ws::vm::{utf8_width, RuntimeError, StepResult, Vm};
+
+/// The state of a machine, when [`Vm::resume`] returns.
+#[derive(Debug)]
+pub enum StepOutcome {
+    /// The program executed `end`.
+    Halted,
+    /// Execution stopped before the instruction at a breakpoint.
+    Breakpoint,
+    /// The next instruction reads input, but not enough is buffered. Resume
+    /// with more input, or end the input with [`Vm::end_input`].
+    NeedInput,
+    /// The program trapped.
+    Trapped(RuntimeError),
+}
+
+/// Input that has been supplied to [`Vm::resume`], but not yet read, and
+/// output that has not yet been taken.
+#[derive(Clone, Debug, Default)]
+pub(super) struct StreamBuffers {
+    input: Vec<u8>,
+    ended: bool,
+    output: Vec<u8>,
+}
+
+impl Vm {
+    /// Appends to the buffered input and executes the program, until it
+    /// halts, traps, stops at a breakpoint, or needs more input than is
+    /// buffered. Unlike [`Vm::run`], this never blocks waiting for input, so
+    /// it suits callers where input arrives over time, such as UIs and async
+    /// runtimes. Output is buffered until taken with [`Vm::take_output`].
+    ///
+    /// `readc` waits until every byte of the UTF-8 sequence is buffered and
+    /// `readi` waits until a whole line, terminated by LF, is buffered, so a
+    /// number split across calls is read whole. Once the input is ended with
+    /// [`Vm::end_input`], reads instead take whatever remains, then follow the
+    /// EOF policy.
+    pub fn resume(&mut self, input: &[u8]) -> StepOutcome {
+        let mut stream = mem::take(&mut self.stream);
+        stream.input.extend_from_slice(input);
+        let mut rest = &stream.input[..];
+        let outcome = loop {
+            if !stream.ended && self.needs_input(rest) {
+                break StepOutcome::NeedInput;
+            }
+            match self.step(&mut rest, &mut stream.output) {
+                Ok(StepResult::Continue) => {}
+                Ok(StepResult::Halted) => break StepOutcome::Halted,
+                Ok(StepResult::Breakpoint) => break StepOutcome::Breakpoint,
+                Err(err) => break StepOutcome::Trapped(err),
+            }
+        };
+        let read = stream.input.len() - rest.len();
+        stream.input.drain(..read);
+        self.stream = stream;
+        outcome
+    }
+
+    /// Marks the input supplied to [`Vm::resume`] as complete, so that
+    /// reading past it follows the EOF policy instead of waiting.
+    #[inline]
+    pub fn end_input(&mut self) {
+        self.stream.ended = true;
+    }
+
+    /// Takes the output written since it was last taken.
+    #[inline]
+    #[must_use]
+    pub fn take_output(&mut self) -> Vec<u8> {
+        mem::take(&mut self.stream.output)
+    }
+
+    /// Whether the next step would read more input than is buffered.
+    fn needs_input(&self, buffered: &[u8]) -> bool {
+        // A breakpoint or stack underflow stops execution before the read.
+        let at_breakpoint = !self.at_breakpoint && self.breakpoints.contains(&self.pc);
+        if self.halted || at_breakpoint || self.stack.is_empty() {
+            return false;
+        }
+        match self.insts.get(self.pc) {
+            Some(Inst::Readc) => match buffered.first() {
+                Some(&b) => buffered.len() < utf8_width(b),
+                None => true,
+            },
+            Some(Inst::Readi) => !buffered.contains(&b'\n'),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Write};
+
+use rug::Integer;
+
+use crate::ws::inst::RawInst;
+use crate::ws::program::InstText;
+use crate::ws::syntax::NumberEncoding;
+
+/// The writer for execution traces, set with
+/// [`Vm::set_trace_writer`](crate::ws::vm::Vm::set_trace_writer).
+#[derive(Default)]
+pub(super) struct Trace {
+    writer: Option<Box<dyn Write>>,
+}
+
+impl Trace {
+    #[inline]
+    pub fn set(&mut self, writer: Option<Box<dyn Write>>) -> Option<Box<dyn Write>> {
+        std::mem::replace(&mut self.writer, writer)
+    }
+
+    /// Writes a line for an instruction that is about to execute, if tracing
+    /// is enabled, with integer arguments decoded with the encoding.
+    pub fn record(
+        &mut self,
+        pc: usize,
+        inst: &RawInst,
+        stack: &[Integer],
+        encoding: NumberEncoding,
+    ) -> io::Result<()> {
+        let w = match &mut self.writer {
+            Some(w) => w,
+            None => return Ok(()),
+        };
+        write!(w, "{pc}\t{}", InstText(inst, encoding))?;
+        match stack.last() {
+            Some(top) => writeln!(w, "\t{}\t{top}", stack.len()),
+            None => writeln!(w, "\t0\t-"),
+        }
+    }
+}
+
+impl Clone for Trace {
+    /// Writers cannot be cloned, so the clone does not trace.
+    #[inline]
+    fn clone(&self) -> Self {
+        Trace::default()
+    }
+}
+
+impl Debug for Trace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Trace")
+            .field("enabled", &self.writer.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::cell::RefCell;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, BufRead, Read, Write};
+
+use bstr::BStr;
+
+use crate::ws::vm::{RuntimeError, Vm};
+
+/// A byte read or written by a program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IoEvent {
+    /// A byte read from the input, by `readc` or `readi`.
+    In(u8),
+    /// A byte written to the output, by `printc` or `printi`.
+    Out(u8),
+}
+
+/// The input read and output written by a program, in the order that it
+/// happened, as recorded by [`Vm::run_transcript`].
+///
+/// It is displayed for golden files with a line for each run of input,
+/// starting with `<`, or of output, starting with `>`, escaped as a string.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Transcript {
+    /// Every byte read or written, in order.
+    pub events: Vec<IoEvent>,
+}
+
+impl Display for Transcript {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut events = self.events.iter().peekable();
+        while let Some(&first) = events.next() {
+            let (dir, b) = match first {
+                IoEvent::In(b) => ('<', b),
+                IoEvent::Out(b) => ('>', b),
+            };
+            let mut run = vec![b];
+            while let Some(&&event) = events.peek() {
+                match (first, event) {
+                    (IoEvent::In(_), IoEvent::In(b)) | (IoEvent::Out(_), IoEvent::Out(b)) => {
+                        run.push(b);
+                    }
+                    _ => break,
+                }
+                events.next();
+            }
+            writeln!(f, "{dir} {:?}", BStr::new(&run))?;
+        }
+        Ok(())
+    }
+}
+
+impl Vm {
+    /// Executes the program with the given input until it halts, traps, or
+    /// stops at a breakpoint, and records the order in which it read input
+    /// and wrote output, for snapshot tests of interactive programs.
+    pub fn run_transcript(&mut self, input: &[u8]) -> (Transcript, Result<(), RuntimeError>) {
+        let events = RefCell::new(Vec::new());
+        let mut input = RecordInput { rest: input, events: &events };
+        let mut output = RecordOutput { events: &events };
+        let res = self.run(&mut input, &mut output);
+        (Transcript { events: events.into_inner() }, res)
+    }
+}
+
+struct RecordInput<'a> {
+    rest: &'a [u8],
+    events: &'a RefCell<Vec<IoEvent>>,
+}
+
+impl Read for RecordInput<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.rest.read(buf)?;
+        (self.events.borrow_mut()).extend(buf[..n].iter().map(|&b| IoEvent::In(b)));
+        Ok(n)
+    }
+}
+
+impl BufRead for RecordInput<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.rest)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let (read, rest) = self.rest.split_at(amt);
+        (self.events.borrow_mut()).extend(read.iter().map(|&b| IoEvent::In(b)));
+        self.rest = rest;
+    }
+}
+
+struct RecordOutput<'a> {
+    events: &'a RefCell<Vec<IoEvent>>,
+}
+
+impl Write for RecordOutput<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (self.events.borrow_mut()).extend(buf.iter().map(|&b| IoEvent::Out(b)));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright (C) 2022 Thalia Archibald
+//
+// Nebula 2 is free software: you can redistribute it and/or modify it under the
+// terms of the GNU Lesser General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version. You should have received a copy of the GNU Lesser General
+// Public License along with Nebula 2. If not, see http://www.gnu.org/licenses/.
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+
+use rug::Integer;
+
+/// A callback for a store to a watched heap address, which is given the value
+/// being stored.
+pub type HeapWatch = Box<dyn FnMut(&Integer)>;
+
+/// A callback for the stack depth crossing a watched threshold, which is
+/// given the new depth.
+pub type StackWatch = Box<dyn FnMut(usize)>;
+
+/// Watchpoints registered on a machine.
+#[derive(Default)]
+pub(super) struct Watches {
+    heap: HashMap<Integer, HeapWatch>,
+    stack: Vec<(usize, StackWatch)>,
+}
+
+impl Watches {
+    #[inline]
+    pub fn set_heap(&mut self, addr: Integer, cb: HeapWatch) {
+        self.heap.insert(addr, cb);
+    }
+
+    #[inline]
+    pub fn set_stack(&mut self, depth: usize, cb: StackWatch) {
+        self.stack.retain(|&(d, _)| d != depth);
+        self.stack.push((depth, cb));
+    }
+
+    /// Fires the watch for a store to `addr`, if any.
+    #[inline]
+    pub fn store(&mut self, addr: &Integer, value: &Integer) {
+        if let Some(cb) = self.heap.get_mut(addr) {
+            cb(value);
+        }
+    }
+
+    /// Fires the watches for thresholds that are crossed by the stack depth
+    /// changing from `old` to `new`.
+    #[inline]
+    pub fn resize_stack(&mut self, old: usize, new: usize) {
+        if old == new {
+            return;
+        }
+        for (depth, cb) in &mut self.stack {
+            if (old < *depth) != (new < *depth) {
+                cb(new);
+            }
+        }
+    }
+}
+
+impl Clone for Watches {
+    /// Callbacks cannot be cloned, so the clone has no watches.
+    #[inline]
+    fn clone(&self) -> Self {
+        Watches::default()
+    }
+}
+
+impl Debug for Watches {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut heap = self.heap.keys().collect::<Vec<_>>();
+        heap.sort_unstable();
+        let stack = self.stack.iter().map(|(d, _)| d).collect::<Vec<_>>();
+        f.debug_struct("Watches")
+            .field("heap", &heap)
+            .field("stack", &stack)
+            .finish()
+    }
+}